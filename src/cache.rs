@@ -51,6 +51,10 @@ impl CachesManager {
         self.force || duration > self.update_interval
     }
 
+    pub fn set_force(&mut self, force: bool) {
+        self.force = force;
+    }
+
     pub async fn flush_meta(&mut self) -> anyhow::Result<()> {
         let last_modified = SystemTime::now()
             .duration_since(SystemTime::UNIX_EPOCH)
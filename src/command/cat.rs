@@ -1,93 +1,133 @@
-use std::io::Cursor;
+use clap::{Args, ValueEnum};
+use serde::{Deserialize, Serialize};
 
-use clap::Args;
-use html2text::{self, render::text_renderer::RichAnnotation};
+use crate::{
+    context::Context,
+    entity::{Docset, LinkTarget},
+    html, render,
+    utils::pager::{pager_command, print_paged},
+};
 
-use crate::{context::Context, entity::Docset};
+use super::{resolve_docset, Command};
 
-use super::Command;
+#[derive(ValueEnum, Deserialize, Clone, Debug, Copy)]
+#[serde(rename_all = "kebab-case")]
+pub enum PageFormat {
+    /// Plain text.
+    Text,
+    /// Text with ANSI colours.
+    Ansi,
+    /// Markdown, keeping links and code blocks.
+    Markdown,
+    /// The html of the page, as stored.
+    Html,
+    /// The title, plain text, links and code blocks of the page.
+    Json,
+}
+
+/// A link of a page, with where it resolves to.
+#[derive(Serialize)]
+struct Reference {
+    text: String,
+    href: String,
+    #[serde(flatten)]
+    target: LinkTarget,
+}
+
+#[derive(Serialize)]
+struct Page<'a> {
+    slug: &'a str,
+    path: &'a str,
+    title: String,
+    body: String,
+    links: Vec<Reference>,
+    code_blocks: Vec<html::CodeBlock>,
+}
+
+/// Collect the links of a page, resolved against its slug and path.
+fn references(slug: &str, path: &str, content: &str) -> Vec<Reference> {
+    html::links(content)
+        .into_iter()
+        .map(|link| Reference {
+            target: LinkTarget::resolve(slug, path, &link.href),
+            text: link.text,
+            href: link.href,
+        })
+        .collect()
+}
+
+/// Format the links of a page as a numbered list, for `--follow`.
+fn format_references(references: &[Reference]) -> String {
+    if references.is_empty() {
+        return String::new();
+    }
+    let mut ret = String::from("\nReferences:\n");
+    for (i, reference) in references.iter().enumerate() {
+        ret.push_str(&format!(
+            "[{}] {}: {}\n",
+            i + 1,
+            reference.text,
+            reference.target
+        ));
+    }
+    ret
+}
+
+/// Render the html of a page in `format`.
+pub(crate) fn render_page(
+    slug: &str,
+    path: &str,
+    content: String,
+    format: PageFormat,
+    width: usize,
+) -> anyhow::Result<String> {
+    let references = references(slug, path, &content);
+    Ok(match format {
+        PageFormat::Text => render::render_text(&content, width)? + &format_references(&references),
+        PageFormat::Ansi => render::render_ansi(&content, width)? + &format_references(&references),
+        PageFormat::Markdown => render::render_markdown(&content),
+        PageFormat::Json => {
+            let page = Page {
+                slug,
+                path,
+                title: html::title(&content).unwrap_or_else(|| path.to_string()),
+                body: render::render_text(&content, width)?,
+                links: references,
+                code_blocks: html::code_blocks(&content),
+            };
+            serde_json::to_string_pretty(&page)? + "\n"
+        }
+        PageFormat::Html => content,
+    })
+}
 
 #[derive(Args, Clone, Debug)]
 pub struct CatArgs {
-    /// The docset to display.
+    /// The docset to display, e.g. `python~3.12`, `python@3.12` or `python` for the newest.
     slug: String,
-    /// The doc page to display.
+    /// The doc page to display. If it has a `#fragment`, only that section is displayed.
     path: String,
+    /// Only display the section under the heading with this text.
+    #[arg(long)]
+    section: Option<String>,
     /// Do not try to update the docset if the expected page is not found.
     #[arg(short, long, default_value = "false")]
     no_update: bool,
     /// Max width of the output.
     #[arg(short, long, default_value = "160")]
     width: usize,
-}
-
-fn default_colour_map(annotations: &[RichAnnotation], s: &str) -> String {
-    use termion::color::*;
-    use RichAnnotation::*;
-    let mut have_explicit_colour = true;
-    let mut start = Vec::new();
-    let mut finish = Vec::new();
-    for annotation in annotations.iter() {
-        match annotation {
-            Default => {}
-            Link(_) => {
-                start.push(format!("{}", termion::style::Underline));
-                finish.push(format!("{}", termion::style::Reset));
-            }
-            Image(_) => {
-                if !have_explicit_colour {
-                    start.push(format!("{}", Fg(Blue)));
-                    finish.push(format!("{}", Fg(Reset)));
-                }
-            }
-            Emphasis => {
-                start.push(format!("{}", termion::style::Bold));
-                finish.push(format!("{}", termion::style::Reset));
-            }
-            Strong => {
-                if !have_explicit_colour {
-                    start.push(format!("{}", Fg(LightYellow)));
-                    finish.push(format!("{}", Fg(Reset)));
-                }
-            }
-            Strikeout => {
-                if !have_explicit_colour {
-                    start.push(format!("{}", Fg(LightBlack)));
-                    finish.push(format!("{}", Fg(Reset)));
-                }
-            }
-            Code => {
-                if !have_explicit_colour {
-                    start.push(format!("{}", Fg(Blue)));
-                    finish.push(format!("{}", Fg(Reset)));
-                }
-            }
-            Preformat(_) => {
-                if !have_explicit_colour {
-                    start.push(format!("{}", Fg(Blue)));
-                    finish.push(format!("{}", Fg(Reset)));
-                }
-            }
-            Colour(c) => {
-                start.push(format!("{}", Fg(Rgb(c.r, c.g, c.b))));
-                finish.push(format!("{}", Fg(Reset)));
-                have_explicit_colour = true;
-            }
-            BgColour(c) => {
-                start.push(format!("{}", Bg(Rgb(c.r, c.g, c.b))));
-                finish.push(format!("{}", Bg(Reset)));
-            }
-            _ => {}
-        }
-    }
-    // Reverse the finish sequences
-    finish.reverse();
-    let mut result = start.join("");
-    result.push_str(s);
-    for s in finish {
-        result.push_str(&s);
-    }
-    result
+    /// The output format.
+    #[arg(short, long, default_value = "ansi", value_enum)]
+    format: PageFormat,
+    /// Always page the output, even if stdout is not a terminal.
+    #[arg(long, default_value = "false", conflicts_with = "no_pager")]
+    pager: bool,
+    /// Never page the output.
+    #[arg(long, default_value = "false")]
+    no_pager: bool,
+    /// Display the page of the N-th reference instead, or print its url if it's off-site.
+    #[arg(long, value_name = "N")]
+    follow: Option<usize>,
 }
 
 #[async_trait::async_trait]
@@ -95,24 +135,41 @@ impl Command for CatArgs {
     async fn run(&self, context: &mut Context) -> anyhow::Result<()> {
         let docsets = Docset::try_to_fetch_docsets(context).await?;
 
-        let doc = docsets
-            .iter()
-            .find(|docset| docset.slug == self.slug)
-            .ok_or_else(|| anyhow::anyhow!("docset {} not found", self.slug))?;
+        let mut doc = resolve_docset(context, &docsets, &self.slug)?;
+        let mut path = self.path.clone();
 
-        let page_path =
-            context.build_cache_path(format!("{}/db/{}/_index", doc.base_directory(), self.path));
-
-        let content = tokio::fs::read_to_string(&page_path).await?;
+        let mut content = doc.read_entry(context, &path).await?;
+        if let Some(section) = &self.section {
+            content = html::section_by_heading(&content, section)
+                .ok_or_else(|| anyhow::anyhow!("section {} not found in {}", section, path))?;
+        }
+        if let Some(n) = self.follow {
+            let mut references = references(&doc.slug, &path, &content);
+            if n == 0 || n > references.len() {
+                anyhow::bail!("{} has {} references, no [{}]", path, references.len(), n);
+            }
+            match references.swap_remove(n - 1).target {
+                LinkTarget::Page { slug, path: target } => {
+                    doc = Docset::find(&docsets, &slug)?;
+                    content = doc.read_entry(context, &target).await?;
+                    path = target;
+                }
+                LinkTarget::External { url } => {
+                    println!("{}", url);
+                    return Ok(());
+                }
+            }
+        }
 
-        let config = html2text::config::rich()
-            .use_doc_css()
-            .max_wrap_width(self.width);
-        let ret = config.coloured(Cursor::new(&content), self.width, move |anns, s| {
-            default_colour_map(anns, s)
-        })?;
+        let ret = render_page(&doc.slug, &path, content, self.format, self.width)?;
 
-        print!("{}", ret);
+        let force = match (self.pager, self.no_pager) {
+            (true, _) => Some(true),
+            (_, true) => Some(false),
+            _ => None,
+        };
+        let pager = pager_command(&context.config, force);
+        print_paged(&ret, pager.as_deref())?;
 
         Ok(())
     }
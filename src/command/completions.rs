@@ -0,0 +1,356 @@
+use std::collections::BTreeSet;
+
+use clap::{Arg, Args, CommandFactory, ValueEnum};
+
+use crate::{
+    context::Context,
+    entity::{Docset, InstallStatus, Resolved},
+};
+
+use super::Command;
+
+#[derive(ValueEnum, Clone, Debug, Copy)]
+pub enum Shell {
+    Bash,
+    Zsh,
+    Fish,
+}
+
+/// What a positional argument is completed with.
+#[derive(Clone, Debug, Copy, PartialEq, Eq)]
+enum Candidates {
+    /// The slugs in the cached `docsets.json`.
+    Slugs,
+    /// The entry paths of a docset.
+    Paths,
+}
+
+/// Arguments named `SLUG`, `SLUGS`, ... are completed with slugs, and `PATH` with the
+/// paths of the docset given before it.
+fn candidates(arg: &Arg) -> Option<Candidates> {
+    let name = value_name(arg);
+    if name.starts_with("SLUG") {
+        Some(Candidates::Slugs)
+    } else if name == "PATH" {
+        Some(Candidates::Paths)
+    } else {
+        None
+    }
+}
+
+fn value_name(arg: &Arg) -> String {
+    arg.get_value_names()
+        .and_then(|names| names.first())
+        .map(|name| name.to_string())
+        .unwrap_or_else(|| arg.get_id().as_str().to_uppercase())
+}
+
+/// Whether the argument takes every following position too.
+fn is_repeated(arg: &Arg) -> bool {
+    arg.get_num_args().is_some_and(|n| n.max_values() > 1)
+}
+
+/// The subcommand and the positional arguments given before the word being completed.
+#[derive(Debug, Default)]
+struct Position<'a> {
+    command: Option<&'a clap::Command>,
+    positionals: Vec<&'a str>,
+}
+
+/// Find the position of the word at `index` of `words`, the command line starting with
+/// `dq`. Options are skipped with the values they take, either in the same word or the
+/// next one. Bash splits `--limit=3` into three words, so a lone `=` is skipped too.
+/// `None` if the word is an option or the value of one.
+fn position<'a>(cli: &'a clap::Command, words: &'a [String], index: usize) -> Option<Position<'a>> {
+    let mut position = Position::default();
+    let mut options_done = false;
+    let mut i = 1;
+    while i < index {
+        let Some(word) = words.get(i).map(String::as_str) else {
+            break;
+        };
+        i += 1;
+        let command = position.command.unwrap_or(cli);
+        let takes_value = if options_done || word == "-" || !word.starts_with('-') {
+            match position.command {
+                None => {
+                    position.command = cli
+                        .get_subcommands()
+                        .find(|command| command.get_name() == word && !command.is_hide_set());
+                    // Anything but a subcommand can't be completed.
+                    position.command?;
+                }
+                Some(_) => position.positionals.push(word),
+            }
+            false
+        } else if word == "--" {
+            options_done = true;
+            false
+        } else if let Some(long) = word.strip_prefix("--") {
+            !long.contains('=')
+                && command
+                    .get_arguments()
+                    .find(|arg| arg.get_long() == Some(long))
+                    .is_some_and(|arg| arg.get_action().takes_values())
+        } else {
+            // A cluster of short flags, the first one that takes a value takes the rest
+            // of the word, or the next word.
+            let shorts = &word[1..];
+            shorts
+                .char_indices()
+                .find(|(_, short)| {
+                    command
+                        .get_arguments()
+                        .find(|arg| arg.get_short() == Some(*short))
+                        .is_some_and(|arg| arg.get_action().takes_values())
+                })
+                .is_some_and(|(at, short)| at + short.len_utf8() == shorts.len())
+        };
+        if takes_value {
+            if words.get(i).is_some_and(|word| word == "=") {
+                i += 1;
+            }
+            i += 1;
+        }
+    }
+    let current = words.get(index).map_or("", String::as_str);
+    if i > index || (!options_done && current.starts_with('-')) {
+        return None;
+    }
+    Some(position)
+}
+
+/// What to complete: the slugs, or the paths of a docset.
+#[derive(Debug, PartialEq, Eq)]
+enum Completion<'a> {
+    Slugs,
+    Paths(&'a str),
+}
+
+/// What the word at `index` of `words` is completed with. `is_docset` tells whether a
+/// word names a docset.
+///
+/// For arguments like `SLUGS... KEYWORD`, slugs are completed only while every word
+/// before names docsets, as the first word that doesn't is the keyword.
+fn completion<'a>(
+    cli: &'a clap::Command,
+    words: &'a [String],
+    index: usize,
+    is_docset: impl Fn(&str) -> bool,
+) -> Option<Completion<'a>> {
+    let position = position(cli, words, index)?;
+    let mut before = position.positionals.as_slice();
+    let arg = position.command?.get_positionals().find(|arg| {
+        if is_repeated(arg) {
+            return true;
+        }
+        match before.split_first() {
+            Some((_, rest)) => {
+                before = rest;
+                false
+            }
+            None => true,
+        }
+    })?;
+    match candidates(arg)? {
+        Candidates::Slugs => {
+            let names_docsets = |word: &&str| {
+                word.split(',')
+                    .filter(|slug| !slug.is_empty())
+                    .all(&is_docset)
+            };
+            if value_name(arg).ends_with("KEYWORD") && !before.iter().all(names_docsets) {
+                return None;
+            }
+            Some(Completion::Slugs)
+        }
+        Candidates::Paths => Some(Completion::Paths(position.positionals.first()?)),
+    }
+}
+
+/// Print what `dq complete` finds for the word being completed, or fall back to the
+/// completion generated by clap, `_dq_static`, if it finds nothing.
+const BASH_DYNAMIC: &str = r#"
+_dq() {
+    local candidates
+    candidates="$(dq complete --index "$COMP_CWORD" -- "${COMP_WORDS[@]}" 2>/dev/null)"
+    if [[ -n "$candidates" ]]; then
+        local IFS=$'\n'
+        COMPREPLY=($(compgen -W "$candidates" -- "${COMP_WORDS[COMP_CWORD]}"))
+        return 0
+    fi
+    _dq_static "$@"
+}
+"#;
+
+const ZSH_DYNAMIC: &str = r#"
+_dq() {
+    local -a candidates
+    candidates=(${(f)"$(dq complete --index $((CURRENT - 1)) -- "${words[@]}" 2>/dev/null)"})
+    if (( ${#candidates} )); then
+        compadd -a candidates
+        return
+    fi
+    _dq_static "$@"
+}
+"#;
+
+const FISH_DYNAMIC: &str = r#"
+function __dq_dynamic
+    set -l words (commandline -opc)
+    dq complete --index (count $words) -- $words (commandline -ct) 2>/dev/null
+end
+
+complete -c dq -a '(__dq_dynamic)'
+"#;
+
+/// Generate the completion script of a shell, with slugs and paths completed by calling
+/// `dq complete`.
+fn generate(shell: Shell) -> String {
+    let mut cli = crate::Cli::command();
+    let clap_shell = match shell {
+        Shell::Bash => clap_complete::Shell::Bash,
+        Shell::Zsh => clap_complete::Shell::Zsh,
+        Shell::Fish => clap_complete::Shell::Fish,
+    };
+    let mut script = vec![];
+    clap_complete::generate(clap_shell, &mut cli, "dq", &mut script);
+    let script = String::from_utf8(script).unwrap();
+
+    match shell {
+        Shell::Bash | Shell::Zsh => {
+            // The script registers `_dq`, which is now the wrapper.
+            let script = format!("\n{}", script).replacen("\n_dq() {", "\n_dq_static() {", 1);
+            let script = &script[1..];
+            match shell {
+                Shell::Bash => format!("{}{}", script, BASH_DYNAMIC),
+                // zsh runs the rest of the file when it's autoloaded, so define the
+                // wrapper before that.
+                _ => match script.find("\nif [ \"$funcstack[1]\" = \"_dq\" ]") {
+                    Some(dispatch) => format!(
+                        "{}{}{}",
+                        &script[..dispatch],
+                        ZSH_DYNAMIC,
+                        &script[dispatch..]
+                    ),
+                    None => format!("{}{}", script, ZSH_DYNAMIC),
+                },
+            }
+        }
+        Shell::Fish => format!("{}{}", script, FISH_DYNAMIC),
+    }
+}
+
+#[derive(Args, Clone, Debug)]
+pub struct CompletionsArgs {
+    /// The shell to generate the completion script for.
+    #[arg(value_enum)]
+    shell: Shell,
+}
+
+#[async_trait::async_trait]
+impl Command for CompletionsArgs {
+    async fn run(&self, _context: &mut Context) -> anyhow::Result<()> {
+        print!("{}", generate(self.shell));
+        Ok(())
+    }
+}
+
+/// Print the candidates of the word being completed one per line, only from the cache and
+/// without any error, as it's called by the completion scripts on every tab.
+#[derive(Args, Clone, Debug)]
+pub struct CompleteArgs {
+    /// The index of the word being completed in `words`.
+    #[arg(long)]
+    index: usize,
+    /// The words of the command line, starting with `dq`.
+    #[arg(last = true)]
+    words: Vec<String>,
+}
+
+#[async_trait::async_trait]
+impl Command for CompleteArgs {
+    async fn run(&self, context: &mut Context) -> anyhow::Result<()> {
+        let Ok(docsets) = context
+            .read_from_cache::<Vec<Docset>, _>("docsets.json")
+            .await
+        else {
+            return Ok(());
+        };
+        let mut cli = crate::Cli::command();
+        cli.build();
+        let is_docset = |slug: &str| Docset::resolve(context, &docsets, slug).is_ok();
+        match completion(&cli, &self.words, self.index, is_docset) {
+            Some(Completion::Slugs) => {
+                for docset in docsets.iter() {
+                    println!("{}", docset.slug);
+                }
+            }
+            Some(Completion::Paths(slug)) => {
+                let Ok(Resolved { docset, .. }) = Docset::resolve(context, &docsets, slug) else {
+                    return Ok(());
+                };
+                if docset.install_status(context) != InstallStatus::Installed {
+                    return Ok(());
+                }
+                let Ok(index) = docset.read_compact_index(context).await else {
+                    return Ok(());
+                };
+                let paths = index
+                    .iter()
+                    .map(|entry| entry.path)
+                    .collect::<BTreeSet<_>>();
+                for path in paths {
+                    println!("{}", path);
+                }
+            }
+            None => {}
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn completion_skips_options() {
+        let mut cli = crate::Cli::command();
+        cli.build();
+        let is_docset = |slug: &str| ["python~3.12", "python", "javascript"].contains(&slug);
+        let cases = [
+            ("dq ", None),
+            ("dq list ", None),
+            ("dq complete ", None),
+            ("dq search ", Some(Completion::Slugs)),
+            ("dq search --offline py", Some(Completion::Slugs)),
+            ("dq search --limit 3 ", Some(Completion::Slugs)),
+            ("dq search -n 3 --type Method ", Some(Completion::Slugs)),
+            ("dq search -n3 ", Some(Completion::Slugs)),
+            ("dq search python,javascript ", Some(Completion::Slugs)),
+            ("dq search python print ", None),
+            ("dq search --", None),
+            ("dq search --format ", None),
+            ("dq update -- -", Some(Completion::Slugs)),
+            ("dq types ", Some(Completion::Slugs)),
+            ("dq types python ", None),
+            ("dq cat python ", Some(Completion::Paths("python"))),
+            (
+                "dq --limit=3 cat -w 80 python ",
+                Some(Completion::Paths("python")),
+            ),
+            (
+                "dq --limit = 3 cat python ",
+                Some(Completion::Paths("python")),
+            ),
+            ("dq --limit = ", None),
+            ("dq cat python page ", None),
+        ];
+        for (line, expected) in cases {
+            let words = line.split(' ').map(str::to_string).collect::<Vec<_>>();
+            let actual = completion(&cli, &words, words.len() - 1, is_docset);
+            assert_eq!(actual, expected, "{:?}", line);
+        }
+    }
+}
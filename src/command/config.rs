@@ -0,0 +1,300 @@
+use std::path::Path;
+
+use clap::{Args, Subcommand};
+use toml_edit::DocumentMut;
+
+use crate::{config::Config, context::Context};
+
+use super::Command;
+
+#[derive(Subcommand, Clone, Debug)]
+enum ConfigCommand {
+    /// Print the value of a key, or its default if it's not set.
+    Get { key: String },
+    /// Set a key. The value is parsed as toml, or taken as a string if that doesn't fit
+    /// the key; a list can also be given separated by commas.
+    Set { key: String, value: String },
+    /// Remove a key, so its default is used.
+    Unset { key: String },
+    /// List every key, with the unset ones commented out with their defaults, if any.
+    List,
+    /// Print the path of the config file.
+    Path,
+    /// Open the config file in `$VISUAL` or `$EDITOR`, then check it.
+    Edit,
+}
+
+#[derive(Args, Clone, Debug)]
+pub struct ConfigArgs {
+    #[command(subcommand)]
+    command: ConfigCommand,
+}
+
+/// The keys of the config file, taken from the fields of [`Config`].
+fn keys() -> Vec<String> {
+    match serde_json::to_value(Config::default()) {
+        Ok(serde_json::Value::Object(fields)) => fields.keys().cloned().collect(),
+        _ => vec![],
+    }
+}
+
+fn check_key(key: &str) -> anyhow::Result<()> {
+    let keys = keys();
+    if !keys.iter().any(|k| k == key) {
+        anyhow::bail!("unknown key {}, the keys are: {}", key, keys.join(", "));
+    }
+    Ok(())
+}
+
+fn read_document(path: &Path) -> anyhow::Result<DocumentMut> {
+    let text = match std::fs::read_to_string(path) {
+        Ok(text) => text,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => String::new(),
+        Err(e) => anyhow::bail!("Read {}, err: {}", path.display(), e),
+    };
+    text.parse()
+        .map_err(|e| anyhow::anyhow!("Parse {}, err: {}", path.display(), e))
+}
+
+fn write_document(path: &Path, document: &DocumentMut) -> anyhow::Result<()> {
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)
+            .map_err(|e| anyhow::anyhow!("Create {}, err: {}", parent.display(), e))?;
+    }
+    std::fs::write(path, document.to_string())
+        .map_err(|e| anyhow::anyhow!("Write {}, err: {}", path.display(), e))
+}
+
+/// Check that `value` fits the type of `key` in [`Config`].
+fn check_value(key: &str, value: &toml_edit::Value) -> anyhow::Result<()> {
+    let mut document = DocumentMut::new();
+    document[key] = toml_edit::value(value.clone());
+    toml::from_str::<Config>(&document.to_string())
+        .map(|_| ())
+        .map_err(|e| anyhow::anyhow!("{}", e.message()))
+}
+
+/// Parse the value given to `set`: as toml, then as a string, then as a list of strings
+/// separated by commas. The first one that fits the key wins.
+fn parse_value(key: &str, raw: &str) -> anyhow::Result<toml_edit::Value> {
+    let candidates = [
+        raw.parse::<toml_edit::Value>().ok(),
+        Some(raw.into()),
+        Some(
+            raw.split(',')
+                .map(str::trim)
+                .filter(|item| !item.is_empty())
+                .collect::<toml_edit::Array>()
+                .into(),
+        ),
+    ];
+    let mut first_error = None;
+    for mut value in candidates.into_iter().flatten() {
+        match check_value(key, &value) {
+            Ok(()) => {
+                value.decor_mut().clear();
+                return Ok(value);
+            }
+            Err(e) => {
+                first_error.get_or_insert(e);
+            }
+        }
+    }
+    Err(anyhow::anyhow!(
+        "invalid value {} for {}, err: {}",
+        raw,
+        key,
+        first_error.unwrap()
+    ))
+}
+
+/// Check that every path of a list can be read, so a bad path isn't only found by the
+/// next download.
+fn check_files(value: &toml_edit::Value) -> anyhow::Result<()> {
+    for path in value
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter_map(|v| v.as_str())
+    {
+        std::fs::File::open(path).map_err(|e| anyhow::anyhow!("Read {}, err: {}", path, e))?;
+    }
+    Ok(())
+}
+
+/// Set `key` to `value`, keeping the comments and whitespace around an existing value.
+fn set_value(document: &mut DocumentMut, key: &str, mut value: toml_edit::Value) {
+    match document
+        .get_mut(key)
+        .and_then(toml_edit::Item::as_value_mut)
+    {
+        Some(old) => {
+            *value.decor_mut() = old.decor().clone();
+            *old = value;
+        }
+        None => document[key] = toml_edit::value(value),
+    }
+}
+
+/// Check the whole config file, warning about the keys that dq doesn't know.
+fn check_document(path: &Path, document: &DocumentMut) -> anyhow::Result<()> {
+    let keys = keys();
+    for (key, _) in document.iter() {
+        if !keys.iter().any(|k| k == key) {
+            eprintln!("warning: unknown key {} in {}", key, path.display());
+        }
+    }
+    toml::from_str::<Config>(&document.to_string())
+        .map_err(|e| anyhow::anyhow!("Parse {}, err: {}", path.display(), e))?;
+    Ok(())
+}
+
+/// Format a value of the config for `get` and `list`, strings without their quotes.
+fn format_value(value: &serde_json::Value) -> String {
+    match value {
+        serde_json::Value::String(s) => s.clone(),
+        value => value.to_string(),
+    }
+}
+
+fn edit(path: &Path) -> anyhow::Result<()> {
+    if !path.exists() {
+        write_document(path, &DocumentMut::new())?;
+    }
+    let editor = std::env::var("VISUAL")
+        .or_else(|_| std::env::var("EDITOR"))
+        .unwrap_or_else(|_| "vi".to_string());
+    let status = std::process::Command::new("sh")
+        .arg("-c")
+        .arg(format!("{} \"$1\"", editor))
+        .arg("sh")
+        .arg(path)
+        .status()
+        .map_err(|e| anyhow::anyhow!("Run editor {}, err: {}", editor, e))?;
+    if !status.success() {
+        anyhow::bail!("editor {} exited with {}", editor, status);
+    }
+    check_document(path, &read_document(path)?)
+}
+
+#[async_trait::async_trait]
+impl Command for ConfigArgs {
+    async fn run(&self, _context: &mut Context) -> anyhow::Result<()> {
+        let path = Config::path();
+        match &self.command {
+            ConfigCommand::Get { key } => {
+                check_key(key)?;
+                let document = read_document(&path)?;
+                let config = toml::from_str::<Config>(&document.to_string())
+                    .map_err(|e| anyhow::anyhow!("Parse {}, err: {}", path.display(), e))?;
+                let values = serde_json::to_value(config.with_defaults())?;
+                match &values[key.as_str()] {
+                    serde_json::Value::Null => {}
+                    value => println!("{}", format_value(value)),
+                }
+            }
+            ConfigCommand::Set { key, value } => {
+                check_key(key)?;
+                let value = parse_value(key, value)?;
+                if key == "ca_certs" {
+                    check_files(&value)?;
+                }
+                let mut document = read_document(&path)?;
+                set_value(&mut document, key, value);
+                write_document(&path, &document)?;
+            }
+            ConfigCommand::Unset { key } => {
+                let mut document = read_document(&path)?;
+                if document.remove(key).is_none() {
+                    check_key(key)?;
+                    return Ok(());
+                }
+                write_document(&path, &document)?;
+            }
+            ConfigCommand::List => {
+                let document = read_document(&path)?;
+                check_document(&path, &document)?;
+                let defaults = serde_json::to_value(Config::default().with_defaults())?;
+                for key in keys() {
+                    match document.get(&key).and_then(|item| item.as_value()) {
+                        Some(value) => {
+                            // Without the comments around it.
+                            let mut value = value.clone();
+                            value.decor_mut().clear();
+                            println!("{} = {}", key, value)
+                        }
+                        None => match &defaults[key.as_str()] {
+                            serde_json::Value::Null => println!("# {} has no default", key),
+                            value => println!("# {} = {}", key, value),
+                        },
+                    }
+                }
+            }
+            ConfigCommand::Path => println!("{}", path.display()),
+            ConfigCommand::Edit => edit(&path)?,
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_value_fits_the_key() {
+        let cases = [
+            ("limit", "3", Some("3")),
+            ("limit", "three", None),
+            ("progress", "false", Some("false")),
+            (
+                "meta_url",
+                "https://example.com",
+                Some("\"https://example.com\""),
+            ),
+            ("user_agent", "3", Some("\"3\"")),
+            ("pager", "", Some("\"\"")),
+            ("storage", "archive", Some("\"archive\"")),
+            ("storage", "zip", None),
+            ("no_proxy", "a.com, b.com", Some("[\"a.com\", \"b.com\"]")),
+            ("no_proxy", "[\"a.com\"]", Some("[\"a.com\"]")),
+        ];
+        for (key, raw, expected) in cases {
+            let actual = parse_value(key, raw).ok().map(|value| value.to_string());
+            assert_eq!(actual.as_deref(), expected, "{} = {}", key, raw);
+        }
+    }
+
+    #[test]
+    fn check_value_checks_the_type() {
+        assert!(check_value("limit", &3.into()).is_ok());
+        assert!(check_value("limit", &"3".into()).is_err());
+        assert!(check_value("retries", &(-1).into()).is_err());
+        assert!(check_value("offline", &true.into()).is_ok());
+        assert!(check_value("ca_certs", &"a.pem".into()).is_err());
+    }
+
+    #[test]
+    fn check_files_reads_every_path() {
+        let file = tempfile::NamedTempFile::new().unwrap();
+        let path = file.path().to_str().unwrap();
+        assert!(check_files(&parse_value("ca_certs", path).unwrap()).is_ok());
+        let paths = format!("{},/nonexistent.pem", path);
+        assert!(check_files(&parse_value("ca_certs", &paths).unwrap()).is_err());
+    }
+
+    #[test]
+    fn set_value_keeps_comments() {
+        let mut document = "# dq\nlimit = 3 # inline\n".parse::<DocumentMut>().unwrap();
+        set_value(&mut document, "limit", parse_value("limit", "8").unwrap());
+        set_value(
+            &mut document,
+            "offline",
+            parse_value("offline", "true").unwrap(),
+        );
+        assert_eq!(
+            document.to_string(),
+            "# dq\nlimit = 8 # inline\noffline = true\n"
+        );
+    }
+}
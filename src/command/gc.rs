@@ -0,0 +1,71 @@
+use clap::Args;
+
+use crate::{context::Context, entity::Docset};
+
+use super::{remove::remove_directories, Command};
+
+#[derive(Args, Clone, Debug)]
+pub struct GcArgs {
+    /// Only print what would be removed.
+    #[arg(long, default_value = "false")]
+    dry_run: bool,
+}
+
+/// The directories of the versions of `docset` to remove, among the `versions` found in
+/// the cache: all but the one of the current `mtime`.
+fn outdated_versions(docset: &Docset, versions: Vec<String>) -> Vec<String> {
+    let current = docset.mtime.to_string();
+    versions
+        .into_iter()
+        .filter(|version| *version != current)
+        .map(|version| format!("{}/{}", docset.slug, version))
+        .collect()
+}
+
+#[async_trait::async_trait]
+impl Command for GcArgs {
+    async fn run(&self, context: &mut Context) -> anyhow::Result<()> {
+        let docsets = Docset::try_to_fetch_docsets(context).await?;
+
+        let mut directories = vec![];
+        for docset in docsets.iter() {
+            let Ok(dir) = std::fs::read_dir(context.build_cache_path(&docset.slug)) else {
+                continue;
+            };
+            let versions = dir
+                .filter_map(|entry| entry.ok())
+                .filter(|entry| entry.path().is_dir())
+                .map(|entry| entry.file_name().to_string_lossy().to_string())
+                .collect();
+            directories.extend(outdated_versions(docset, versions));
+        }
+        directories.sort();
+
+        remove_directories(context, &directories, self.dry_run).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keeps_the_current_version() {
+        let docset = Docset {
+            name: "Python".to_string(),
+            slug: "python~3.12".to_string(),
+            r#type: "sphinx".to_string(),
+            links: None,
+            version: Some("3.12".to_string()),
+            release: None,
+            mtime: 1700000000,
+            db_size: 0,
+        };
+        let versions = ["1690000000", "1700000000", "1710000000"].map(str::to_string);
+        assert_eq!(
+            outdated_versions(&docset, versions.to_vec()),
+            ["python~3.12/1690000000", "python~3.12/1710000000"]
+        );
+        assert!(outdated_versions(&docset, vec!["1700000000".to_string()]).is_empty());
+    }
+}
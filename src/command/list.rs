@@ -0,0 +1,145 @@
+use clap::Args;
+use indicatif::HumanBytes;
+use stanza::{
+    renderer::{console::Console, Renderer as _},
+    style::{Header, Styles},
+    table::{Row, Table},
+};
+
+use crate::{
+    context::Context,
+    entity::{Docset, InstallStatus},
+};
+
+use super::{Command, OutputFormat};
+
+#[derive(Args, Clone, Debug)]
+pub struct ListArgs {
+    /// Only list docsets that have been downloaded, in any version.
+    #[arg(long, default_value = "false", conflicts_with_all = ["not_installed", "outdated"])]
+    installed: bool,
+    /// Only list docsets that have never been downloaded.
+    #[arg(long, default_value = "false", conflicts_with = "outdated")]
+    not_installed: bool,
+    /// Only list docsets whose downloaded version is older than the latest one.
+    #[arg(long, default_value = "false")]
+    outdated: bool,
+    /// The output format.
+    #[arg(long, default_value = "text", value_enum)]
+    format: OutputFormat,
+}
+
+impl ListArgs {
+    fn accept(&self, status: InstallStatus) -> bool {
+        if self.installed {
+            status != InstallStatus::NotInstalled
+        } else if self.not_installed {
+            status == InstallStatus::NotInstalled
+        } else if self.outdated {
+            status == InstallStatus::Outdated
+        } else {
+            true
+        }
+    }
+}
+
+fn status_label(status: InstallStatus) -> &'static str {
+    match status {
+        InstallStatus::NotInstalled => "",
+        InstallStatus::Installed => "installed",
+        InstallStatus::Outdated => "outdated",
+    }
+}
+
+trait Outputs {
+    fn output(&self, docsets: &[(&Docset, InstallStatus)]);
+}
+
+struct TextOutput;
+
+impl Outputs for TextOutput {
+    fn output(&self, docsets: &[(&Docset, InstallStatus)]) {
+        for (docset, status) in docsets {
+            println!(
+                "{}\t{}\t{}\t{}\t{}\t{}",
+                docset.slug,
+                docset.name,
+                docset.version.as_deref().unwrap_or_default(),
+                docset.release.as_deref().unwrap_or_default(),
+                docset.db_size,
+                status_label(*status),
+            );
+        }
+    }
+}
+
+struct JsonOutput;
+
+impl Outputs for JsonOutput {
+    fn output(&self, docsets: &[(&Docset, InstallStatus)]) {
+        let docsets = docsets
+            .iter()
+            .map(|(docset, status)| {
+                serde_json::json!({
+                    "docset": docset,
+                    "status": status,
+                })
+            })
+            .collect::<Vec<_>>();
+        println!("{}", serde_json::to_string(&docsets).unwrap());
+    }
+}
+
+struct TableOutput;
+
+impl Outputs for TableOutput {
+    fn output(&self, docsets: &[(&Docset, InstallStatus)]) {
+        let mut table = Table::default().with_row(Row::new(
+            Styles::default().with(Header(true)),
+            vec![
+                "Name".into(),
+                "Slug".into(),
+                "Version".into(),
+                "Release".into(),
+                "Size".into(),
+                "Status".into(),
+            ],
+        ));
+        for (docset, status) in docsets {
+            table.push_row(vec![
+                docset.name.clone(),
+                docset.slug.clone(),
+                docset.version.clone().unwrap_or_default(),
+                docset.release.clone().unwrap_or_default(),
+                HumanBytes(docset.db_size.max(0) as u64).to_string(),
+                status_label(*status).to_string(),
+            ]);
+        }
+        let renderer = Console::default();
+        println!("{}", renderer.render(&table));
+    }
+}
+
+fn to_output(format: OutputFormat) -> Box<dyn Outputs> {
+    match format {
+        OutputFormat::Text => Box::new(TextOutput),
+        OutputFormat::Json => Box::new(JsonOutput),
+        OutputFormat::Table => Box::new(TableOutput),
+    }
+}
+
+#[async_trait::async_trait]
+impl Command for ListArgs {
+    async fn run(&self, context: &mut Context) -> anyhow::Result<()> {
+        let docsets = Docset::try_to_fetch_docsets(context).await?;
+        let docsets = docsets
+            .iter()
+            .map(|docset| (docset, docset.install_status(context)))
+            .filter(|(_, status)| self.accept(*status))
+            .collect::<Vec<_>>();
+
+        to_output(self.format).output(&docsets);
+
+        Ok(())
+    }
+}
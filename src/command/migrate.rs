@@ -0,0 +1,65 @@
+use clap::Args;
+use indicatif::HumanBytes;
+
+use crate::{
+    context::Context,
+    entity::{Docset, InstallStatus},
+    store,
+    utils::fs::dir_size,
+};
+
+use super::{resolve_docset, search::split_slugs, Command};
+
+#[derive(Args, Clone, Debug)]
+pub struct MigrateArgs {
+    /// The docsets to migrate, all installed docsets if not specified.
+    slugs: Vec<String>,
+}
+
+#[async_trait::async_trait]
+impl Command for MigrateArgs {
+    async fn run(&self, context: &mut Context) -> anyhow::Result<()> {
+        let docsets = Docset::try_to_fetch_docsets(context).await?;
+        let slugs = split_slugs(&self.slugs);
+        let selected = if slugs.is_empty() {
+            docsets
+                .iter()
+                .filter(|docset| docset.install_status(context) == InstallStatus::Installed)
+                .collect()
+        } else {
+            slugs
+                .iter()
+                .map(|slug| resolve_docset(context, &docsets, slug))
+                .collect::<anyhow::Result<Vec<_>>>()?
+        };
+
+        let storage = context.config.storage();
+        for docset in selected {
+            let base_directory = context.build_cache_path(docset.base_directory());
+            if docset.install_status(context) != InstallStatus::Installed {
+                anyhow::bail!(
+                    "docset {} is not installed, run `dq update {}` first",
+                    docset.slug,
+                    docset.slug
+                );
+            }
+
+            let before = dir_size(&base_directory);
+            let directory = base_directory.clone();
+            let migrated = tokio::task::spawn_blocking(move || store::migrate(&directory, storage))
+                .await?
+                .map_err(|e| anyhow::anyhow!("Migrate {}, err: {}", docset.slug, e))?;
+            match migrated {
+                Some(count) => println!(
+                    "Migrated {}: {} pages, {} -> {}",
+                    docset.slug,
+                    count,
+                    HumanBytes(before),
+                    HumanBytes(dir_size(&base_directory))
+                ),
+                None => println!("{} is already stored as {}", docset.slug, storage),
+            }
+        }
+        Ok(())
+    }
+}
@@ -1,8 +1,41 @@
-use crate::context::Context;
+use clap::ValueEnum;
+
+use crate::{context::Context, entity::Docset};
 
 pub mod update;
 pub mod search;
 pub mod cat;
+pub mod list;
+pub mod remove;
+pub mod gc;
+pub mod pick;
+pub mod verify;
+pub mod migrate;
+pub mod types;
+pub mod serve;
+pub mod completions;
+pub mod config;
+
+#[derive(ValueEnum, Clone, Debug, Copy)]
+pub enum OutputFormat {
+    Text,
+    Json,
+    Table,
+}
+
+/// Resolve a docset given on the command line, telling which one is used when it's not
+/// the exact slug.
+pub(crate) fn resolve_docset<'a>(
+    context: &Context,
+    docsets: &'a [Docset],
+    query: &str,
+) -> anyhow::Result<&'a Docset> {
+    let resolved = Docset::resolve(context, docsets, query)?;
+    if resolved.fallback {
+        eprintln!("Using {} for {}", resolved.docset.slug, query);
+    }
+    Ok(resolved.docset)
+}
 
 #[async_trait::async_trait]
 pub trait Command {
@@ -0,0 +1,278 @@
+use std::{
+    collections::HashMap,
+    io::Write,
+    os::fd::{AsRawFd, RawFd},
+};
+
+use clap::Args;
+use fuzzy_matcher::FuzzyMatcher;
+use termion::{
+    clear, cursor::Goto, event::Key, input::TermRead, raw::IntoRawMode,
+    screen::IntoAlternateScreen, style,
+};
+
+use crate::{
+    context::Context,
+    entity::{CompactIndex, Docset},
+    render,
+};
+
+use super::{
+    resolve_docset,
+    search::{match_entries, select_docsets, split_slugs, Matcher, SearchResult},
+    Command,
+};
+
+#[derive(Args, Clone, Debug)]
+pub struct PickArgs {
+    /// The matcher to use.
+    #[arg(long, default_value = "skim-matcher-v2", value_enum)]
+    matcher: Matcher,
+    /// Pick from every installed docset.
+    #[arg(long, default_value = "false")]
+    installed: bool,
+    /// Print the slug and path of the chosen entry instead of displaying it.
+    #[arg(short, long, default_value = "false")]
+    print: bool,
+    /// The initial query.
+    #[arg(short, long, default_value = "")]
+    query: String,
+    /// Max width of the output.
+    #[arg(short, long, default_value = "160")]
+    width: usize,
+    /// The docsets to pick from, separated by spaces or commas.
+    slugs: Vec<String>,
+}
+
+/// The size of the terminal behind `fd`, which is not necessarily stdout.
+fn terminal_size(fd: RawFd) -> (u16, u16) {
+    let mut size: libc::winsize = unsafe { std::mem::zeroed() };
+    if unsafe { libc::ioctl(fd, libc::TIOCGWINSZ, &mut size) } == 0 && size.ws_col > 0 {
+        (size.ws_col, size.ws_row)
+    } else {
+        (80, 24)
+    }
+}
+
+/// Truncate `s` to at most `width` chars.
+fn truncate(s: &str, width: usize) -> &str {
+    match s.char_indices().nth(width) {
+        Some((i, _)) => &s[..i],
+        None => s,
+    }
+}
+
+struct Picker<'a> {
+    context: &'a Context,
+    docsets: Vec<&'a Docset>,
+    indexes: Vec<CompactIndex>,
+    matcher: Box<dyn FuzzyMatcher>,
+    query: String,
+    results: Vec<SearchResult<'a>>,
+    /// The selected result.
+    selected: usize,
+    /// The first visible result.
+    scroll: usize,
+    /// The first visible line of the preview.
+    preview_scroll: usize,
+    /// Rendered previews, keyed by slug and path.
+    previews: HashMap<(&'a str, String), Vec<String>>,
+}
+
+impl<'a> Picker<'a> {
+    fn refresh(&mut self) {
+        let mut results = self
+            .docsets
+            .iter()
+            .zip(self.indexes.iter())
+            .flat_map(|(&docset, index)| {
+                match_entries(
+                    self.matcher.as_ref(),
+                    &docset.slug,
+                    index,
+                    &self.query,
+                    None,
+                )
+            })
+            .collect::<Vec<_>>();
+        results.sort_by(|a, b| b.score.partial_cmp(&a.score).unwrap());
+        self.results = results;
+        self.selected = 0;
+        self.scroll = 0;
+        self.preview_scroll = 0;
+    }
+
+    fn select(&mut self, selected: usize) {
+        if selected < self.results.len() {
+            self.selected = selected;
+            self.preview_scroll = 0;
+        }
+    }
+
+    /// Render the page of the selected result, must be called inside `block_in_place`.
+    fn preview(&mut self, width: usize) -> Vec<String> {
+        let Some(result) = self.results.get(self.selected) else {
+            return vec![];
+        };
+        let key = (result.slug, result.entry.path.clone());
+        if let Some(lines) = self.previews.get(&key) {
+            return lines.clone();
+        }
+
+        let docset = self.docsets.iter().find(|d| d.slug == result.slug).unwrap();
+        let rendered = tokio::runtime::Handle::current()
+            .block_on(docset.read_entry(self.context, &result.entry.path))
+            .and_then(|content| render::render_ansi(&content, width));
+        let lines = match rendered {
+            Ok(text) => text.lines().map(str::to_string).collect(),
+            Err(e) => vec![e.to_string()],
+        };
+        self.previews.insert(key, lines.clone());
+        lines
+    }
+
+    fn draw(&mut self, out: &mut impl Write, (width, height): (u16, u16)) -> anyhow::Result<()> {
+        let (width, height) = (width as usize, height as usize);
+        let rows = height.saturating_sub(2);
+        let list_width = (width * 2 / 5).max(20).min(width);
+        let preview_x = list_width + 3;
+        let preview_width = width.saturating_sub(preview_x).max(1);
+
+        if self.selected < self.scroll {
+            self.scroll = self.selected;
+        } else if rows > 0 && self.selected >= self.scroll + rows {
+            self.scroll = self.selected + 1 - rows;
+        }
+        let preview = self.preview(preview_width);
+        let multiple = self.docsets.len() > 1;
+
+        for row in 0..rows {
+            write!(out, "{}{}", Goto(1, row as u16 + 2), clear::CurrentLine)?;
+            if let Some(result) = self.results.get(self.scroll + row) {
+                let label = if multiple {
+                    format!("{}: {}", result.slug, result.entry.name)
+                } else {
+                    result.entry.name.clone()
+                };
+                let label = truncate(&label, list_width);
+                if self.scroll + row == self.selected {
+                    write!(out, "{}{}{}", style::Invert, label, style::Reset)?;
+                } else {
+                    write!(out, "{}", label)?;
+                }
+            }
+            write!(out, "{}│", Goto(list_width as u16 + 2, row as u16 + 2))?;
+            if let Some(line) = preview.get(self.preview_scroll + row) {
+                write!(out, " {}{}", line, style::Reset)?;
+            }
+        }
+
+        write!(
+            out,
+            "{}{}{}{} matches{}",
+            Goto(1, height as u16),
+            clear::CurrentLine,
+            style::Faint,
+            self.results.len(),
+            style::Reset
+        )?;
+        write!(
+            out,
+            "{}{}> {}",
+            Goto(1, 1),
+            clear::CurrentLine,
+            truncate(&self.query, width.saturating_sub(2))
+        )?;
+        out.flush()?;
+        Ok(())
+    }
+
+    /// Run the picker until an entry is chosen, returning its slug and path.
+    fn run(mut self) -> anyhow::Result<Option<(String, String)>> {
+        let input = termion::get_tty()?;
+        let fd = input.as_raw_fd();
+        let mut screen = termion::get_tty()?
+            .into_raw_mode()?
+            .into_alternate_screen()?;
+
+        self.refresh();
+        self.draw(&mut screen, terminal_size(fd))?;
+
+        for key in input.keys() {
+            let page = (terminal_size(fd).1 as usize / 2).max(1);
+            match key? {
+                Key::Esc | Key::Ctrl('c') | Key::Ctrl('g') => return Ok(None),
+                Key::Char('\n') => {
+                    return Ok(self
+                        .results
+                        .get(self.selected)
+                        .map(|result| (result.slug.to_string(), result.entry.path.clone())));
+                }
+                Key::Up | Key::Ctrl('p') => self.select(self.selected.saturating_sub(1)),
+                Key::Down | Key::Ctrl('n') => self.select(self.selected + 1),
+                Key::PageDown | Key::Ctrl('d') => self.preview_scroll += page,
+                Key::PageUp | Key::Ctrl('u') => {
+                    self.preview_scroll = self.preview_scroll.saturating_sub(page)
+                }
+                Key::Backspace => {
+                    self.query.pop();
+                    self.refresh();
+                }
+                Key::Char(c) if !c.is_control() => {
+                    self.query.push(c);
+                    self.refresh();
+                }
+                _ => {}
+            }
+            self.draw(&mut screen, terminal_size(fd))?;
+        }
+
+        Ok(None)
+    }
+}
+
+#[async_trait::async_trait]
+impl Command for PickArgs {
+    async fn run(&self, context: &mut Context) -> anyhow::Result<()> {
+        let docsets = Docset::try_to_fetch_docsets(context).await?;
+        let context = &*context;
+        let slugs = split_slugs(&self.slugs);
+        let named = slugs
+            .iter()
+            .map(|slug| resolve_docset(context, &docsets, slug))
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        let selected = select_docsets(context, &docsets, named, self.installed)?;
+        let indexes = futures::future::try_join_all(
+            selected
+                .iter()
+                .map(|docset| docset.read_compact_index(context)),
+        )
+        .await?;
+
+        let picker = Picker {
+            context,
+            docsets: selected,
+            indexes,
+            matcher: self.matcher.to_matcher(),
+            query: self.query.clone(),
+            results: vec![],
+            selected: 0,
+            scroll: 0,
+            preview_scroll: 0,
+            previews: HashMap::new(),
+        };
+        let Some((slug, path)) = tokio::task::block_in_place(|| picker.run())? else {
+            return Ok(());
+        };
+
+        if self.print {
+            println!("{}\t{}", slug, path);
+        } else {
+            let docset = Docset::find(&docsets, &slug)?;
+            let content = docset.read_entry(context, &path).await?;
+            print!("{}", render::render_ansi(&content, self.width)?);
+        }
+
+        Ok(())
+    }
+}
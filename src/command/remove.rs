@@ -0,0 +1,114 @@
+use clap::Args;
+use indicatif::HumanBytes;
+
+use crate::{
+    context::Context,
+    entity::{Docset, Manifest},
+    utils::fs::dir_size,
+};
+
+use super::Command;
+
+#[derive(Args, Clone, Debug)]
+pub struct RemoveArgs {
+    /// Only print what would be removed.
+    #[arg(long, default_value = "false")]
+    dry_run: bool,
+    /// The docsets to remove, all of their versions will be deleted.
+    #[arg(required = true)]
+    slugs: Vec<String>,
+}
+
+/// Remove the given directories of the cache, then print how many bytes were freed.
+pub(crate) async fn remove_directories(
+    context: &Context,
+    directories: &[String],
+    dry_run: bool,
+) -> anyhow::Result<()> {
+    let mut freed = 0;
+    for directory in directories {
+        let path = context.build_cache_path(directory);
+        let size = dir_size(&path);
+        if dry_run {
+            println!("Would remove {} ({})", directory, HumanBytes(size));
+        } else {
+            tokio::fs::remove_dir_all(&path)
+                .await
+                .map_err(|e| anyhow::anyhow!("Remove {}, err: {}", path.display(), e))?;
+            println!("Removed {} ({})", directory, HumanBytes(size));
+        }
+        freed += size;
+    }
+    if dry_run {
+        println!("Would free {}", HumanBytes(freed));
+    } else {
+        println!("Freed {}", HumanBytes(freed));
+    }
+    Ok(())
+}
+
+#[async_trait::async_trait]
+impl Command for RemoveArgs {
+    async fn run(&self, context: &mut Context) -> anyhow::Result<()> {
+        for slug in self.slugs.iter() {
+            if slug.is_empty() || slug.contains(['/', '\\']) || slug == "." || slug == ".." {
+                anyhow::bail!("invalid docset slug: {}", slug);
+            }
+            if !context.build_cache_path(slug).is_dir() {
+                anyhow::bail!("docset {} is not installed", slug);
+            }
+        }
+
+        // Read it before removing anything, so a manifest made from the docsets in the
+        // cache still has the ones that are kept.
+        let docsets = context
+            .read_from_cache::<Vec<Docset>, _>("docsets.json")
+            .await
+            .unwrap_or_default();
+        let mut manifest = Manifest::read(context, &docsets).await?;
+
+        remove_directories(context, &self.slugs, self.dry_run).await?;
+
+        if manifest.remove(&self.slugs) && !self.dry_run {
+            manifest.write(context).await?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeSet;
+
+    use super::*;
+    use crate::utils::testing;
+
+    #[tokio::test]
+    async fn remove_slug_drops_it_from_the_manifest() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut context = testing::context(dir.path()).await;
+        let mut docsets = vec![];
+        for slug in ["a", "b"] {
+            docsets.push(serde_json::json!({
+                "name": slug, "slug": slug, "type": "simple", "links": null,
+                "version": null, "release": null, "mtime": 1700000000, "db_size": 0,
+            }));
+            let directory = context.build_cache_path(format!("{}/1700000000", slug));
+            std::fs::create_dir_all(&directory).unwrap();
+            std::fs::write(directory.join("index.json"), "{}").unwrap();
+        }
+        context
+            .write_to_cache("docsets.json", &docsets)
+            .await
+            .unwrap();
+
+        // There is no manifest yet, so it's made from the cache before removing.
+        testing::parse_args::<RemoveArgs>(&["a"])
+            .run(&mut context)
+            .await
+            .unwrap();
+        let manifest = Manifest::read(&context, &[]).await.unwrap();
+        assert_eq!(manifest.slugs, BTreeSet::from(["b".to_string()]));
+        assert!(!context.build_cache_path("a").exists());
+    }
+}
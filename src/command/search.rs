@@ -1,5 +1,8 @@
+use std::{collections::HashSet, io::IsTerminal};
+
 use clap::{Args, ValueEnum};
 use fuzzy_matcher::FuzzyMatcher;
+use serde::Deserialize;
 use stanza::{
     renderer::{console::Console, Renderer as _},
     style::{Header, Styles},
@@ -8,41 +11,53 @@ use stanza::{
 
 use crate::{
     context::Context,
-    entity::{Docset, Index, IndexEntry},
+    entity::{
+        count_matches, snippet, split_fragment, CompactIndex, Docset, IndexEntry, InstallStatus,
+        Snippet,
+    },
+    html,
 };
 
-use super::Command;
+use super::{resolve_docset, Command, OutputFormat};
 
-#[derive(ValueEnum, Clone, Debug, Copy)]
-enum Matcher {
+#[derive(ValueEnum, Deserialize, Clone, Debug, Copy, Default)]
+#[serde(rename_all = "kebab-case")]
+pub(crate) enum Matcher {
     SkimMatcherV1,
+    #[default]
     SkimMatcherV2,
     Clangd,
 }
 
-#[derive(ValueEnum, Clone, Debug, Copy)]
-enum OutputFormat {
-    Text,
-    Json,
-    Table,
-}
-
 #[derive(Args, Clone, Debug)]
 pub struct SearchArgs {
     /// The matcher to use.
     #[arg(long, default_value = "skim-matcher-v2", value_enum)]
     matcher: Matcher,
-    /// The docset to search.
-    slug: String,
-    /// The query to search.
-    keyword: String,
+    /// Search in every installed docset.
+    #[arg(long, default_value = "false")]
+    installed: bool,
+    /// Only search entries of this type, fuzzy-matched against the types of each docset.
+    /// Can be repeated.
+    #[arg(short, long = "type", value_name = "TYPE")]
+    types: Vec<String>,
+    /// Search the text of the pages instead of the names of the entries.
+    #[arg(long, default_value = "false")]
+    full_text: bool,
+    /// The max number of results, 20 by default with `--full-text`.
+    #[arg(short = 'n', long)]
+    max_results: Option<usize>,
+    /// The docsets to search, separated by spaces or commas, followed by the query to search.
+    /// With `--installed`, only the query is needed.
+    #[arg(required = true, value_name = "SLUGS... KEYWORD")]
+    args: Vec<String>,
     /// The output format.
     #[arg(long, default_value = "text", value_enum)]
     format: OutputFormat,
 }
 
 impl Matcher {
-    fn to_matcher(self) -> Box<dyn FuzzyMatcher> {
+    pub(crate) fn to_matcher(self) -> Box<dyn FuzzyMatcher> {
         match self {
             #[allow(deprecated)]
             Matcher::SkimMatcherV1 => Box::new(fuzzy_matcher::skim::SkimMatcher::default()),
@@ -52,16 +67,69 @@ impl Matcher {
     }
 }
 
+pub(crate) struct SearchResult<'a> {
+    pub(crate) slug: &'a str,
+    pub(crate) entry: IndexEntry,
+    pub(crate) score: f64,
+    /// The matching text, for full-text searches.
+    pub(crate) snippet: Option<Snippet>,
+}
+
+impl SearchResult<'_> {
+    pub(crate) fn to_json(&self) -> serde_json::Value {
+        let mut value = serde_json::json!({
+            "slug": self.slug,
+            "entry": self.entry,
+            "score": self.score,
+        });
+        if let Some(snippet) = &self.snippet {
+            value["snippet"] = serde_json::json!(snippet);
+        }
+        value
+    }
+}
+
+/// Wrap the highlighted parts of a snippet in ANSI colours.
+fn highlight(snippet: &Snippet) -> String {
+    let mut ret = String::new();
+    let mut last = 0;
+    for &(start, end) in snippet.highlights.iter() {
+        ret.push_str(&snippet.text[last..start]);
+        ret.push_str(&format!(
+            "{}{}{}{}",
+            termion::style::Bold,
+            termion::color::Fg(termion::color::Yellow),
+            &snippet.text[start..end],
+            termion::style::Reset
+        ));
+        last = end;
+    }
+    ret.push_str(&snippet.text[last..]);
+    ret
+}
+
 trait Outputs {
-    fn output(&self, entries: &[(&IndexEntry, i64)]);
+    fn output(&self, results: &[SearchResult], multiple: bool);
 }
 
 struct TextOutput;
 
 impl Outputs for TextOutput {
-    fn output(&self, entries: &[(&IndexEntry, i64)]) {
-        for (entry, _) in entries {
-            println!("{}", entry.path);
+    fn output(&self, results: &[SearchResult], multiple: bool) {
+        let colour = std::io::stdout().is_terminal();
+        for result in results {
+            if multiple {
+                println!("{}\t{}", result.slug, result.entry.path);
+            } else {
+                println!("{}", result.entry.path);
+            }
+            if let Some(snippet) = &result.snippet {
+                if colour {
+                    println!("    {}", highlight(snippet));
+                } else {
+                    println!("    {}", snippet.text);
+                }
+            }
         }
     }
 }
@@ -69,35 +137,44 @@ impl Outputs for TextOutput {
 struct JsonOutput;
 
 impl Outputs for JsonOutput {
-    fn output(&self, entries: &[(&IndexEntry, i64)]) {
-        let entries = entries
+    fn output(&self, results: &[SearchResult], _multiple: bool) {
+        let results = results
             .iter()
-            .map(|(entry, score)| {
-                serde_json::json!({
-                    "entry": entry,
-                    "score": score,
-                })
-            })
+            .map(SearchResult::to_json)
             .collect::<Vec<_>>();
-        println!("{}", serde_json::to_string(&entries).unwrap());
+        println!("{}", serde_json::to_string(&results).unwrap());
     }
 }
 
 struct TableOutput;
 
 impl Outputs for TableOutput {
-    fn output(&self, entries: &[(&IndexEntry, i64)]) {
+    fn output(&self, results: &[SearchResult], _multiple: bool) {
         // build a table model
-        let mut table = Table::default().with_row(Row::new(
-            Styles::default().with(Header(true)),
-            vec!["Name".into(), "Path".into(), "Score".into()],
-        ));
-        for (entry, score) in entries {
-            table.push_row(vec![
-                entry.name.clone(),
-                entry.path.clone(),
-                format!("{:.2}", score),
-            ]);
+        let full_text = results.iter().any(|result| result.snippet.is_some());
+        let mut header = vec!["Slug".into(), "Name".into(), "Path".into(), "Score".into()];
+        if full_text {
+            header.push("Match".into());
+        }
+        let mut table =
+            Table::default().with_row(Row::new(Styles::default().with(Header(true)), header));
+        for result in results {
+            let mut row = vec![
+                result.slug.to_string(),
+                result.entry.name.clone(),
+                result.entry.path.clone(),
+                format!("{:.2}", result.score),
+            ];
+            if full_text {
+                row.push(
+                    result
+                        .snippet
+                        .as_ref()
+                        .map(|snippet| snippet.text.clone())
+                        .unwrap_or_default(),
+                );
+            }
+            table.push_row(row);
         }
         let renderer = Console::default();
         println!("{}", renderer.render(&table));
@@ -114,32 +191,326 @@ impl OutputFormat {
     }
 }
 
+impl SearchArgs {
+    /// Split the positional arguments into the slugs and the keyword.
+    fn slugs_and_keyword(&self) -> (Vec<&str>, &str) {
+        let (keyword, slugs) = self.args.split_last().unwrap();
+        (split_slugs(slugs), keyword)
+    }
+}
+
+/// Split slugs given as separate arguments or comma-separated lists.
+pub(crate) fn split_slugs(args: &[String]) -> Vec<&str> {
+    args.iter()
+        .flat_map(|slug| slug.split(','))
+        .filter(|slug| !slug.is_empty())
+        .collect()
+}
+
+/// Select the `named` docsets, plus every installed docset if `installed` is set.
+pub(crate) fn select_docsets<'a>(
+    context: &Context,
+    docsets: &'a [Docset],
+    mut selected: Vec<&'a Docset>,
+    installed: bool,
+) -> anyhow::Result<Vec<&'a Docset>> {
+    if installed {
+        let named = selected.clone();
+        selected.extend(docsets.iter().filter(|docset| {
+            docset.install_status(context) == InstallStatus::Installed
+                && !named.iter().any(|named| named.slug == docset.slug)
+        }));
+    }
+    if selected.is_empty() {
+        anyhow::bail!("no docsets to search");
+    }
+    Ok(selected)
+}
+
+/// Find the types of an index that best match each of `queries`. An exact match, ignoring
+/// case, wins over fuzzy ones.
+pub(crate) fn match_types<'a>(
+    matcher: &dyn FuzzyMatcher,
+    index: &'a CompactIndex,
+    queries: &[String],
+) -> HashSet<&'a str> {
+    let types = index
+        .iter()
+        .filter_map(|entry| entry.r#type)
+        .collect::<HashSet<_>>();
+    let mut matched = HashSet::new();
+    for query in queries {
+        if let Some(exact) = types.iter().find(|t| t.eq_ignore_ascii_case(query)) {
+            matched.insert(*exact);
+            continue;
+        }
+        let scores = types
+            .iter()
+            .filter_map(|t| matcher.fuzzy_match(t, query).map(|score| (*t, score)))
+            .collect::<Vec<_>>();
+        let best = scores.iter().map(|(_, score)| *score).max();
+        matched.extend(
+            scores
+                .iter()
+                .filter(|(_, score)| Some(*score) == best)
+                .map(|(t, _)| *t),
+        );
+    }
+    matched
+}
+
+/// Match the entries of an index, with scores relative to the score of an exact
+/// match of `keyword`, so that results from different docsets are comparable. If
+/// `types` is given, only entries of these types are matched.
+pub(crate) fn match_entries<'a>(
+    matcher: &dyn FuzzyMatcher,
+    slug: &'a str,
+    index: &CompactIndex,
+    keyword: &str,
+    types: Option<&HashSet<&str>>,
+) -> Vec<SearchResult<'a>> {
+    let exact = matcher.fuzzy_match(keyword, keyword).unwrap_or(0).max(1) as f64;
+    index
+        .iter()
+        .filter(|entry| types.is_none_or(|types| entry.r#type.is_some_and(|t| types.contains(t))))
+        .filter_map(|entry| {
+            matcher
+                .fuzzy_match(entry.name, keyword)
+                .map(|score| SearchResult {
+                    slug,
+                    entry: entry.to_entry(),
+                    score: score as f64 / exact,
+                    snippet: None,
+                })
+        })
+        .collect()
+}
+
+/// The width of the snippets of full-text results, in chars.
+const SNIPPET_WIDTH: usize = 160;
+
+/// Find the entry of `page` whose section matches `keyword` best, and a snippet of it.
+///
+/// A section runs until the next one of the same level, so the smallest section with
+/// the most matches is the one the match belongs to.
+fn locate_match(
+    index: &CompactIndex,
+    page: &str,
+    content: &str,
+    keyword: &str,
+    types: Option<&HashSet<&str>>,
+) -> Option<(IndexEntry, Snippet)> {
+    // Parsed once for all the entries of the page.
+    let document = html::Document::parse(content);
+    let mut best: Option<(IndexEntry, String, usize)> = None;
+    for entry in index
+        .iter()
+        .filter(|entry| split_fragment(entry.path).0 == page)
+    {
+        if types.is_some_and(|types| entry.r#type.is_none_or(|t| !types.contains(t))) {
+            continue;
+        }
+        let text = match split_fragment(entry.path).1 {
+            Some(id) => document.section_text_by_id(id).unwrap_or_default(),
+            None => document.plain_text(),
+        };
+        let matches = count_matches(&text, keyword);
+        let better = match &best {
+            None => true,
+            Some((_, best_text, best_matches)) => {
+                matches > *best_matches
+                    || (matches == *best_matches && text.len() < best_text.len())
+            }
+        };
+        if better {
+            best = Some((entry.to_entry(), text, matches));
+        }
+    }
+
+    match best {
+        Some((entry, text, _)) => Some((entry, snippet(&text, keyword, SNIPPET_WIDTH))),
+        // A page without entries still matches, unless only some types are wanted.
+        None if types.is_none() => {
+            let entry = IndexEntry {
+                name: document.title().unwrap_or_else(|| page.to_string()),
+                path: page.to_string(),
+                r#type: None,
+            };
+            Some((
+                entry,
+                snippet(&document.plain_text(), keyword, SNIPPET_WIDTH),
+            ))
+        }
+        None => None,
+    }
+}
+
+/// Search the names of the entries of `selected`, best first.
+pub(crate) fn search_entries<'a>(
+    matcher: &dyn FuzzyMatcher,
+    selected: &[&'a Docset],
+    indexes: &[&CompactIndex],
+    keyword: &str,
+    types: &[String],
+    max_results: Option<usize>,
+) -> Vec<SearchResult<'a>> {
+    let mut results = selected
+        .iter()
+        .zip(indexes.iter())
+        .flat_map(|(docset, index)| {
+            let types = (!types.is_empty()).then(|| match_types(matcher, index, types));
+            match_entries(matcher, &docset.slug, index, keyword, types.as_ref())
+        })
+        .collect::<Vec<_>>();
+    results.sort_by(|a, b| b.score.partial_cmp(&a.score).unwrap());
+    if let Some(max_results) = max_results {
+        results.truncate(max_results);
+    }
+    results
+}
+
+/// Search the text of the pages of `selected`, ranked with BM25.
+pub(crate) async fn search_full_text<'a>(
+    context: &Context,
+    matcher: &dyn FuzzyMatcher,
+    selected: &[&'a Docset],
+    indexes: &[&CompactIndex],
+    keyword: &str,
+    types: &[String],
+    max_results: usize,
+) -> anyhow::Result<Vec<SearchResult<'a>>> {
+    let mut hits = vec![];
+    for (docset, index) in selected.iter().zip(indexes.iter()) {
+        let fulltext = docset.read_fulltext_index(context).await?;
+        let docset_hits = fulltext.search(keyword);
+        let max = docset_hits.first().map(|hit| hit.score).unwrap_or(0.0);
+        let types = (!types.is_empty()).then(|| match_types(matcher, index, types));
+        for hit in docset_hits {
+            hits.push((*docset, *index, types.clone(), hit.path, hit.score / max));
+        }
+    }
+    hits.sort_by(|a, b| b.4.total_cmp(&a.4));
+
+    let mut results = vec![];
+    for (docset, index, types, page, score) in hits {
+        if results.len() >= max_results {
+            break;
+        }
+        let content = docset.read_page(context, &page).await?;
+        if let Some((entry, snippet)) =
+            locate_match(index, &page, &content, keyword, types.as_ref())
+        {
+            results.push(SearchResult {
+                slug: &docset.slug,
+                entry,
+                score,
+                snippet: Some(snippet),
+            });
+        }
+    }
+    Ok(results)
+}
+
 #[async_trait::async_trait]
 impl Command for SearchArgs {
     async fn run(&self, context: &mut Context) -> anyhow::Result<()> {
         let matcher = self.matcher.to_matcher();
+        let (slugs, keyword) = self.slugs_and_keyword();
         let docsets = Docset::try_to_fetch_docsets(context).await?;
-        let doc = docsets
-            .iter()
-            .find(|docset| docset.slug == self.slug)
-            .ok_or_else(|| anyhow::anyhow!("docset {} not found", self.slug))?;
-        let index_file: Index = context
-            .read_from_cache(format!("{}/index.json", doc.base_directory()))
-            .await?;
-        let mut entries = index_file
-            .entries
+        let named = slugs
             .iter()
-            .filter_map(|entry| {
-                matcher
-                    .fuzzy_match(&entry.name, &self.keyword)
-                    .map(|score| (entry, score))
-            })
-            .collect::<Vec<_>>();
-        entries.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap());
+            .map(|slug| resolve_docset(context, &docsets, slug))
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        let selected = select_docsets(context, &docsets, named, self.installed)?;
+        let indexes = futures::future::try_join_all(
+            selected
+                .iter()
+                .map(|docset| docset.read_compact_index(context)),
+        )
+        .await?;
+
+        let indexes = indexes.iter().collect::<Vec<_>>();
+
+        let results = if self.full_text {
+            search_full_text(
+                context,
+                matcher.as_ref(),
+                &selected,
+                &indexes,
+                keyword,
+                &self.types,
+                self.max_results.unwrap_or(20),
+            )
+            .await?
+        } else {
+            search_entries(
+                matcher.as_ref(),
+                &selected,
+                &indexes,
+                keyword,
+                &self.types,
+                self.max_results,
+            )
+        };
 
         let outputs = self.format.to_output();
-        outputs.output(&entries);
+        outputs.output(&results, selected.len() > 1);
 
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::entity::{Index, IndexEntry};
+
+    fn docset(slug: &str) -> Docset {
+        Docset {
+            name: slug.to_string(),
+            slug: slug.to_string(),
+            r#type: "simple".to_string(),
+            links: None,
+            version: None,
+            release: None,
+            mtime: 0,
+            db_size: 0,
+        }
+    }
+
+    fn index(names: &[&str]) -> CompactIndex {
+        CompactIndex::from_index(&Index {
+            entries: names
+                .iter()
+                .map(|name| IndexEntry {
+                    name: name.to_string(),
+                    path: name.to_lowercase(),
+                    r#type: None,
+                })
+                .collect(),
+            types: vec![],
+        })
+    }
+
+    #[test]
+    fn scores_compare_across_docsets() {
+        let matcher = Matcher::SkimMatcherV2.to_matcher();
+        let (exact, weak) = (docset("exact"), docset("weak"));
+        let indexes = [index(&["push", "pop"]), index(&["p_u_s_h_all"])];
+        let results = search_entries(
+            matcher.as_ref(),
+            &[&weak, &exact],
+            &[&indexes[1], &indexes[0]],
+            "push",
+            &[],
+            None,
+        );
+        let ranked = results
+            .iter()
+            .map(|result| (result.slug, result.entry.name.as_str()))
+            .collect::<Vec<_>>();
+        assert_eq!(ranked, [("exact", "push"), ("weak", "p_u_s_h_all")]);
+        assert_eq!(results[0].score, 1.0);
+        assert!(results[1].score < 1.0);
+    }
+}
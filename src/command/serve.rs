@@ -0,0 +1,34 @@
+use std::{net::SocketAddr, sync::Arc};
+
+use clap::{ArgGroup, Args};
+
+use crate::{context::Context, entity::Docset, server::Server};
+
+use super::Command;
+
+#[derive(Args, Clone, Debug)]
+#[command(group(ArgGroup::new("mode").required(true).args(["stdio", "http"])))]
+pub struct ServeArgs {
+    /// Serve JSON-RPC 2.0 over stdin and stdout, one message per line or framed with
+    /// `Content-Length` headers. The methods are `docsets`, `search` and `render`.
+    #[arg(long, default_value = "false")]
+    stdio: bool,
+    /// Serve the installed docsets as html at this address, e.g. `127.0.0.1:8080`, with
+    /// a json search endpoint at `/search?q=KEYWORD&slugs=SLUG,...`.
+    #[arg(long, value_name = "ADDR")]
+    http: Option<SocketAddr>,
+}
+
+#[async_trait::async_trait]
+impl Command for ServeArgs {
+    async fn run(&self, context: &mut Context) -> anyhow::Result<()> {
+        let docsets = Docset::try_to_fetch_docsets(context).await?;
+        // The server owns its context, so the connections can be served on their own tasks.
+        let context = Context::new(context.config.clone()).await?;
+        let server = Arc::new(Server::new(context, docsets));
+        match self.http {
+            Some(addr) => server.serve_http(addr).await,
+            None => server.serve_stdio().await,
+        }
+    }
+}
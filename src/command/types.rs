@@ -0,0 +1,104 @@
+use std::collections::BTreeMap;
+
+use clap::Args;
+use stanza::{
+    renderer::{console::Console, Renderer as _},
+    style::{Header, Styles},
+    table::{Row, Table},
+};
+
+use crate::{
+    context::Context,
+    entity::{Docset, IndexType},
+};
+
+use super::{resolve_docset, Command, OutputFormat};
+
+#[derive(Args, Clone, Debug)]
+pub struct TypesArgs {
+    /// The docset to list the types of.
+    slug: String,
+    /// The output format.
+    #[arg(long, default_value = "text", value_enum)]
+    format: OutputFormat,
+}
+
+trait Outputs {
+    fn output(&self, types: &[IndexType]);
+}
+
+struct TextOutput;
+
+impl Outputs for TextOutput {
+    fn output(&self, types: &[IndexType]) {
+        for r#type in types {
+            println!("{}\t{}", r#type.name, r#type.count);
+        }
+    }
+}
+
+struct JsonOutput;
+
+impl Outputs for JsonOutput {
+    fn output(&self, types: &[IndexType]) {
+        println!("{}", serde_json::to_string(types).unwrap());
+    }
+}
+
+struct TableOutput;
+
+impl Outputs for TableOutput {
+    fn output(&self, types: &[IndexType]) {
+        let mut table = Table::default().with_row(Row::new(
+            Styles::default().with(Header(true)),
+            vec!["Name".into(), "Count".into(), "Slug".into()],
+        ));
+        for r#type in types {
+            table.push_row(vec![
+                r#type.name.clone(),
+                r#type.count.to_string(),
+                r#type.slug.clone(),
+            ]);
+        }
+        let renderer = Console::default();
+        println!("{}", renderer.render(&table));
+    }
+}
+
+fn to_output(format: OutputFormat) -> Box<dyn Outputs> {
+    match format {
+        OutputFormat::Text => Box::new(TextOutput),
+        OutputFormat::Json => Box::new(JsonOutput),
+        OutputFormat::Table => Box::new(TableOutput),
+    }
+}
+
+#[async_trait::async_trait]
+impl Command for TypesArgs {
+    async fn run(&self, context: &mut Context) -> anyhow::Result<()> {
+        let docsets = Docset::try_to_fetch_docsets(context).await?;
+        let docset = resolve_docset(context, &docsets, &self.slug)?;
+        let mut index = docset.read_index(context).await?;
+
+        if index.types.is_empty() {
+            // Count them from the entries, for indexes without the list of types.
+            let mut counts = BTreeMap::new();
+            for entry in index.entries.iter() {
+                if let Some(name) = &entry.r#type {
+                    *counts.entry(name.clone()).or_insert(0) += 1;
+                }
+            }
+            index.types = counts
+                .into_iter()
+                .map(|(name, count)| IndexType {
+                    slug: name.to_lowercase().replace(' ', "-"),
+                    name,
+                    count,
+                })
+                .collect();
+        }
+
+        to_output(self.format).output(&index.types);
+        Ok(())
+    }
+}
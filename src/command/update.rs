@@ -3,9 +3,12 @@ use std::collections::HashSet;
 use clap::Args;
 use futures::{stream::FuturesUnordered, StreamExt};
 
-use crate::{context::Context, entity::Docset};
+use crate::{
+    context::Context,
+    entity::{Docset, Manifest},
+};
 
-use super::Command;
+use super::{resolve_docset, Command};
 
 #[derive(Args, Clone, Debug)]
 pub struct UpdateArgs {
@@ -19,14 +22,39 @@ pub struct UpdateArgs {
     /// Update all docsets instead of the specified ones.
     #[arg(long, default_value = "false")]
     all: bool,
-    /// If specified, only update the specified docsets.
+    /// The docsets to install or update. Without any, the installed docsets are updated.
     slugs: Vec<String>,
 }
 
 #[async_trait::async_trait]
 impl Command for UpdateArgs {
     async fn run(&self, context: &mut Context) -> anyhow::Result<()> {
+        if self.force {
+            context.config.force = Some(true);
+            context.caches.set_force(true);
+        }
         let docsets = Docset::try_to_fetch_docsets(context).await?;
+        let mut manifest = Manifest::read(context, &docsets).await?;
+        let slugs = if self.all {
+            HashSet::new()
+        } else if self.slugs.is_empty() {
+            for slug in manifest.slugs.iter() {
+                if Docset::find(&docsets, slug).is_err() {
+                    eprintln!("warning: {} is installed but no longer available", slug);
+                }
+            }
+            manifest.slugs.iter().cloned().collect()
+        } else {
+            let slugs = self
+                .slugs
+                .iter()
+                .map(|slug| Ok(resolve_docset(context, &docsets, slug)?.slug.clone()))
+                .collect::<anyhow::Result<HashSet<_>>>()?;
+            // Keep the docsets even if their download fails, so the next update retries them.
+            manifest.add(slugs.iter().cloned());
+            manifest.write(context).await?;
+            slugs
+        };
         let pb = context.bar.add_root();
         pb.update_style(
         indicatif::ProgressStyle::default_bar()
@@ -36,7 +64,6 @@ impl Command for UpdateArgs {
             .unwrap(),
         );
 
-        let slugs = self.slugs.iter().collect::<HashSet<_>>();
         let filter = |docset: &&Docset| {
             if self.all {
                 true
@@ -58,7 +85,7 @@ impl Command for UpdateArgs {
         let mut futures = FuturesUnordered::new();
 
         loop {
-            while futures.len() < context.config.limit.unwrap_or(5) {
+            while futures.len() < context.config.limit() {
                 let docset = match iter.next() {
                     Some(docset) => docset,
                     None => break,
@@ -79,3 +106,70 @@ impl Command for UpdateArgs {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeSet;
+
+    use super::*;
+    use crate::{
+        store::{self, Storage},
+        utils::testing,
+    };
+
+    #[tokio::test]
+    async fn update_slug_adds_it_to_the_manifest() {
+        let dir = tempfile::tempdir().unwrap();
+        testing::write_mirror(&dir.path().join("mirror"), &["a", "b"]);
+        let mut context = testing::context(dir.path()).await;
+
+        testing::parse_args::<UpdateArgs>(&["a"])
+            .run(&mut context)
+            .await
+            .unwrap();
+        let manifest = Manifest::read(&context, &[]).await.unwrap();
+        assert_eq!(manifest.slugs, BTreeSet::from(["a".to_string()]));
+        assert!(context.build_cache_path("a/1700000000/index.json").exists());
+        assert!(!context.build_cache_path("b").exists());
+    }
+
+    #[tokio::test]
+    async fn update_all_leaves_the_manifest_alone() {
+        let dir = tempfile::tempdir().unwrap();
+        testing::write_mirror(&dir.path().join("mirror"), &["a", "b"]);
+        let mut context = testing::context(dir.path()).await;
+        let update = |args: &[&str]| testing::parse_args::<UpdateArgs>(args);
+
+        update(&["--all"]).run(&mut context).await.unwrap();
+        assert!(!context.cache_file_exists("installed.json"));
+
+        let manifest = Manifest {
+            slugs: BTreeSet::from(["a".to_string()]),
+        };
+        manifest.write(&context).await.unwrap();
+        update(&["--all"]).run(&mut context).await.unwrap();
+        let manifest = Manifest::read(&context, &[]).await.unwrap();
+        assert_eq!(manifest.slugs, BTreeSet::from(["a".to_string()]));
+        assert!(context.build_cache_path("b/1700000000/index.json").exists());
+    }
+
+    #[tokio::test]
+    async fn update_keeps_a_migrated_storage() {
+        let dir = tempfile::tempdir().unwrap();
+        testing::write_mirror(&dir.path().join("mirror"), &["a"]);
+        let mut context = testing::context(dir.path()).await;
+        let base_directory = context.build_cache_path("a/1700000000");
+
+        testing::parse_args::<UpdateArgs>(&["a"])
+            .run(&mut context)
+            .await
+            .unwrap();
+        store::migrate(&base_directory, Storage::Archive).unwrap();
+        testing::parse_args::<UpdateArgs>(&["a"])
+            .run(&mut context)
+            .await
+            .unwrap();
+        assert_eq!(store::current_storage(&base_directory), Storage::Archive);
+        assert!(!base_directory.join("db").exists());
+    }
+}
@@ -0,0 +1,203 @@
+use std::collections::HashSet;
+
+use clap::Args;
+
+use crate::{
+    context::Context,
+    entity::{split_fragment, Docset, InstallStatus},
+};
+
+use super::{resolve_docset, search::split_slugs, Command};
+
+#[derive(Args, Clone, Debug)]
+pub struct VerifyArgs {
+    /// Unpack the pages of broken docsets again, from the cached `db.json` or a new download.
+    #[arg(long, default_value = "false")]
+    repair: bool,
+    /// Also list the stored pages that no entry of the index points to. Many docsets have
+    /// such pages, so they are not a sign of a broken docset.
+    #[arg(short, long, default_value = "false")]
+    verbose: bool,
+    /// The docsets to verify, all installed docsets if not specified.
+    slugs: Vec<String>,
+}
+
+#[derive(Debug, Default)]
+struct Report {
+    pages: usize,
+    missing: Vec<String>,
+    unreadable: Vec<String>,
+    /// Only filled in verbose mode.
+    orphaned: Vec<String>,
+}
+
+impl Report {
+    fn is_broken(&self) -> bool {
+        !self.missing.is_empty() || !self.unreadable.is_empty()
+    }
+
+    /// The summary line of the docset, then a line per missing, unreadable and, in
+    /// verbose mode, orphaned page.
+    fn lines(&self, slug: &str, verbose: bool) -> Vec<String> {
+        let mut summary = format!(
+            "{}: {} pages, {} missing, {} unreadable",
+            slug,
+            self.pages,
+            self.missing.len(),
+            self.unreadable.len()
+        );
+        if verbose {
+            summary.push_str(&format!(", {} orphaned", self.orphaned.len()));
+        }
+        let mut lines = vec![summary];
+        let orphaned: &[String] = if verbose { &self.orphaned } else { &[] };
+        let pages = [
+            ("missing", self.missing.as_slice()),
+            ("unreadable", self.unreadable.as_slice()),
+            ("orphaned", orphaned),
+        ];
+        for (kind, paths) in pages {
+            lines.extend(paths.iter().map(|path| format!("  {}: {}", kind, path)));
+        }
+        lines
+    }
+
+    fn print(&self, slug: &str, verbose: bool) {
+        for line in self.lines(slug, verbose) {
+            println!("{}", line);
+        }
+    }
+}
+
+/// Check every page referenced by the index of `docset` against the unpacked pages, and
+/// look for the pages out of the index if `verbose` is set.
+async fn check(context: &Context, docset: &Docset, verbose: bool) -> anyhow::Result<Report> {
+    let index = docset.read_index(context).await?;
+    let mut pages = index
+        .entries
+        .iter()
+        .map(|entry| split_fragment(&entry.path).0)
+        .collect::<HashSet<_>>()
+        .into_iter()
+        .collect::<Vec<_>>();
+    pages.sort();
+
+    let mut report = Report {
+        pages: pages.len(),
+        ..Default::default()
+    };
+    let Ok(store) = docset.page_store(context) else {
+        // The archive itself is broken, so none of its pages can be read.
+        report.unreadable = pages.into_iter().map(str::to_string).collect();
+        return Ok(report);
+    };
+    for page in pages.iter() {
+        match store.read(page) {
+            Ok(Some(_)) => {}
+            Ok(None) => report.missing.push(page.to_string()),
+            Err(_) => report.unreadable.push(page.to_string()),
+        }
+    }
+    if !verbose {
+        return Ok(report);
+    }
+
+    report.orphaned = orphaned(store.paths(), &pages);
+    Ok(report)
+}
+
+/// The paths of `stored` that are not in `pages`, sorted.
+fn orphaned(stored: Vec<String>, pages: &[&str]) -> Vec<String> {
+    let pages = pages.iter().copied().collect::<HashSet<_>>();
+    let mut orphaned = stored
+        .into_iter()
+        .filter(|path| !pages.contains(path.as_str()))
+        .collect::<Vec<_>>();
+    orphaned.sort();
+    orphaned
+}
+
+#[async_trait::async_trait]
+impl Command for VerifyArgs {
+    async fn run(&self, context: &mut Context) -> anyhow::Result<()> {
+        let docsets = Docset::try_to_fetch_docsets(context).await?;
+        let slugs = split_slugs(&self.slugs);
+        let selected = if slugs.is_empty() {
+            docsets
+                .iter()
+                .filter(|docset| docset.install_status(context) == InstallStatus::Installed)
+                .collect()
+        } else {
+            slugs
+                .iter()
+                .map(|slug| resolve_docset(context, &docsets, slug))
+                .collect::<anyhow::Result<Vec<_>>>()?
+        };
+
+        let mut broken = 0;
+        for docset in selected {
+            let mut report = check(context, docset, self.verbose).await?;
+            if report.is_broken() && self.repair {
+                report.print(&docset.slug, self.verbose);
+                let pb = context.bar.add_root();
+                pb.set_message(format!("Repairing {}", docset.name));
+                docset.repair(context, &pb).await?;
+                pb.finish(format!("{} repaired", docset.name));
+                report = check(context, docset, self.verbose).await?;
+            }
+            report.print(&docset.slug, self.verbose);
+            if report.is_broken() {
+                broken += 1;
+            }
+        }
+
+        if broken > 0 {
+            anyhow::bail!(
+                "{} docsets are broken, run `dq verify --repair` to fix them",
+                broken
+            );
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn orphaned_pages_are_out_of_the_index() {
+        let stored = ["map", "array", "array/push", "extra"].map(str::to_string);
+        assert_eq!(
+            orphaned(stored.to_vec(), &["array", "map"]),
+            ["array/push", "extra"]
+        );
+    }
+
+    #[test]
+    fn orphaned_pages_are_listed_only_in_verbose_mode() {
+        let report = Report {
+            pages: 2,
+            missing: vec!["map".to_string()],
+            unreadable: vec![],
+            orphaned: vec!["extra".to_string()],
+        };
+        assert_eq!(
+            report.lines("a", false),
+            ["a: 2 pages, 1 missing, 0 unreadable", "  missing: map"]
+        );
+        assert_eq!(
+            report.lines("a", true),
+            [
+                "a: 2 pages, 1 missing, 0 unreadable, 1 orphaned",
+                "  missing: map",
+                "  orphaned: extra",
+            ]
+        );
+        let report = Report {
+            missing: vec![],
+            ..report
+        };
+        assert!(!report.is_broken());
+    }
+}
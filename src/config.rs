@@ -5,6 +5,8 @@ use std::{
 
 use serde::{Deserialize, Serialize};
 
+use crate::store::Storage;
+
 #[derive(Clone, Debug, Serialize, Deserialize, Default)]
 pub struct Config {
     /// The directory where the cache is stored.
@@ -18,8 +20,38 @@ pub struct Config {
     pub force: Option<bool>,
     /// The number of concurrent downloads.
     pub limit: Option<usize>,
+    /// The url of the docsets metadata, `docs.json`.
+    pub meta_url: Option<String>,
+    /// The base url of the documents, which contains `{slug}/index.json` and `{slug}/db.json`.
+    pub documents_url: Option<String>,
+    /// How to store the pages of docsets, `files` or `archive`.
+    pub storage: Option<Storage>,
+    /// Never touch the network, only use what's in the cache.
+    pub offline: Option<bool>,
+    /// The timeout to connect to a server, in seconds.
+    pub connect_timeout: Option<u64>,
+    /// The timeout of each read from a server, in seconds.
+    pub read_timeout: Option<u64>,
+    /// How many times to retry a failed download.
+    pub retries: Option<u32>,
+    /// The proxy for all requests.
+    pub proxy: Option<String>,
+    /// Hosts that bypass `proxy`.
+    pub no_proxy: Option<Vec<String>>,
+    /// Extra root CA certificates to trust, in PEM or DER.
+    pub ca_certs: Option<Vec<PathBuf>>,
+    /// Whether to use the proxies set by the system, like `HTTPS_PROXY`.
+    pub system_proxy: Option<bool>,
+    /// The User-Agent header sent with every request.
+    pub user_agent: Option<String>,
+    /// The pager for long output, overridden by `$DQ_PAGER`. An empty string disables it.
+    pub pager: Option<String>,
 }
 
+const DEFAULT_META_URL: &str = "https://devdocs.io/docs.json";
+const DEFAULT_DOCUMENTS_URL: &str = "https://documents.devdocs.io";
+const DEFAULT_USER_AGENT: &str = concat!("dq/", env!("CARGO_PKG_VERSION"));
+
 static DEFAULT_CACHE_DIR: LazyLock<PathBuf> = LazyLock::new(default_cache_dir);
 fn default_cache_dir() -> PathBuf {
     let base_dir = xdg::BaseDirectories::with_prefix("dq").unwrap();
@@ -37,24 +69,101 @@ impl Config {
         self.progress.unwrap_or(true)
     }
 
+    pub fn limit(&self) -> usize {
+        self.limit.unwrap_or(5)
+    }
+
     pub fn update_interval(&self) -> u64 {
         self.update_interval.unwrap_or(60 * 60 * 24)
     }
 
+    pub fn meta_url(&self) -> &str {
+        self.meta_url.as_deref().unwrap_or(DEFAULT_META_URL)
+    }
+
+    pub fn documents_url(&self) -> &str {
+        self.documents_url
+            .as_deref()
+            .unwrap_or(DEFAULT_DOCUMENTS_URL)
+            .trim_end_matches('/')
+    }
+
+    pub fn storage(&self) -> Storage {
+        self.storage.unwrap_or(Storage::Files)
+    }
+
+    pub fn offline(&self) -> bool {
+        self.offline.unwrap_or(false)
+    }
+
+    pub fn connect_timeout(&self) -> u64 {
+        self.connect_timeout.unwrap_or(10)
+    }
+
+    pub fn read_timeout(&self) -> u64 {
+        self.read_timeout.unwrap_or(30)
+    }
+
+    pub fn retries(&self) -> u32 {
+        self.retries.unwrap_or(3)
+    }
+
+    pub fn no_proxy(&self) -> &[String] {
+        self.no_proxy.as_deref().unwrap_or_default()
+    }
+
+    pub fn ca_certs(&self) -> &[PathBuf] {
+        self.ca_certs.as_deref().unwrap_or_default()
+    }
+
+    pub fn system_proxy(&self) -> bool {
+        self.system_proxy.unwrap_or(true)
+    }
+
+    pub fn user_agent(&self) -> &str {
+        self.user_agent.as_deref().unwrap_or(DEFAULT_USER_AGENT)
+    }
+
     pub fn force(&self) -> bool {
         self.force.unwrap_or(false)
     }
 
-    pub fn new_from_file() -> Self {
-        match Self::load_from_file() {
-            Ok(config) => config,
-            Err(_) => Self::default(),
+    /// A copy with every key that has a default filled in.
+    pub fn with_defaults(&self) -> Self {
+        Self {
+            cache_dir: Some(self.cache_dir().to_path_buf()),
+            progress: Some(self.progress()),
+            update_interval: Some(self.update_interval()),
+            force: Some(self.force()),
+            limit: Some(self.limit()),
+            meta_url: Some(self.meta_url().to_string()),
+            documents_url: Some(self.documents_url().to_string()),
+            storage: Some(self.storage()),
+            offline: Some(self.offline()),
+            connect_timeout: Some(self.connect_timeout()),
+            read_timeout: Some(self.read_timeout()),
+            retries: Some(self.retries()),
+            proxy: self.proxy.clone(),
+            no_proxy: Some(self.no_proxy().to_vec()),
+            ca_certs: Some(self.ca_certs().to_vec()),
+            system_proxy: Some(self.system_proxy()),
+            user_agent: Some(self.user_agent().to_string()),
+            pager: self.pager.clone(),
         }
     }
 
-    fn load_from_file() -> anyhow::Result<Self> {
+    /// The path of the config file, `$XDG_CONFIG_HOME/dq/config.toml`.
+    pub fn path() -> PathBuf {
         let base_dir = xdg::BaseDirectories::with_prefix("dq").unwrap();
-        let config_file = base_dir.get_config_file("config.toml");
+        base_dir.get_config_file("config.toml")
+    }
+
+    pub fn new_from_file() -> Self {
+        Self::load_from_file().unwrap_or_default()
+    }
+
+    fn load_from_file() -> anyhow::Result<Self> {
+        let config_file = Self::path();
         if config_file.exists() {
             let config = std::fs::read_to_string(config_file)?;
             let config = toml::from_str(&config)?;
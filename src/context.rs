@@ -1,17 +1,25 @@
 use std::{
-    env,
     ffi::CString,
     os::fd::FromRawFd,
     path::{Path, PathBuf},
-    sync::Arc,
+    sync::{Arc, OnceLock},
+    time::Duration,
 };
 
 use anyhow::bail;
-use bytes::{Bytes, BytesMut};
+use bytes::Bytes;
 use futures::StreamExt;
-use reqwest::{Client, IntoUrl, Response};
-use serde::{de::DeserializeOwned, Serialize};
-use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use reqwest::{
+    header::{
+        CONTENT_RANGE, ETAG, IF_MODIFIED_SINCE, IF_NONE_MATCH, IF_RANGE, LAST_MODIFIED, RANGE,
+    },
+    Certificate, Client, NoProxy, Proxy, Response, StatusCode, Url,
+};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt, BufWriter},
+    sync::mpsc::Sender,
+};
 
 use crate::{
     cache::CachesManager,
@@ -19,12 +27,144 @@ use crate::{
     utils::progress::{ProgressBar, ProgressBarManager},
 };
 
+/// The receiver of a download has gone, usually because it failed to process the data.
+#[derive(Debug, thiserror::Error)]
+#[error("the download of {0} was aborted by its receiver")]
+pub struct SinkClosed(String);
+
+/// The server closed the connection before sending the whole body.
+#[derive(Debug, thiserror::Error)]
+#[error("Incomplete download of {url}, got {got} of {total} bytes")]
+struct IncompleteDownload {
+    url: String,
+    got: u64,
+    total: u64,
+}
+
+/// Whether a failed download may succeed if tried again, because the server could not be
+/// reached or failed on its side. Client errors like 404 never will.
+pub(crate) fn is_retryable(e: &anyhow::Error) -> bool {
+    if e.is::<IncompleteDownload>() {
+        return true;
+    }
+    e.downcast_ref::<reqwest::Error>()
+        .is_some_and(|e| e.status().is_none_or(|status| status.is_server_error()))
+}
+
+/// The delay before the `attempt`th retry, doubling each time, with some jitter.
+fn backoff(attempt: u32) -> Duration {
+    let max = Duration::from_millis(500) * 2u32.pow(attempt.min(6) - 1);
+    max.mul_f64(0.5 + rand::random::<f64>() / 2.0)
+}
+
+/// Send the part of `chunk`, which starts at `position` in the file, that `sink` has not got
+/// yet. `sent` counts the bytes sent so far, across retries.
+async fn send_new_bytes(
+    sink: &Sender<Bytes>,
+    chunk: Bytes,
+    position: u64,
+    sent: &mut u64,
+) -> Result<(), ()> {
+    let end = position + chunk.len() as u64;
+    if end <= *sent {
+        return Ok(());
+    }
+    let skip = sent.saturating_sub(position) as usize;
+    sink.send(chunk.slice(skip..)).await.map_err(|_| ())?;
+    *sent = end;
+    Ok(())
+}
+
+/// The validators of a cached http response.
+#[derive(Debug, Serialize, Deserialize)]
+struct HttpMeta {
+    url: String,
+    etag: Option<String>,
+    last_modified: Option<String>,
+}
+
+impl HttpMeta {
+    fn new(url: &Url, response: &Response) -> Self {
+        let header = |name| {
+            response
+                .headers()
+                .get(name)
+                .and_then(|value| value.to_str().ok())
+                .map(str::to_string)
+        };
+        Self {
+            url: url.to_string(),
+            etag: header(ETAG),
+            last_modified: header(LAST_MODIFIED),
+        }
+    }
+
+    /// The validator to resume a download with, weak etags can not be used in `If-Range`.
+    fn if_range(&self) -> Option<&str> {
+        self.etag
+            .as_deref()
+            .filter(|etag| !etag.starts_with("W/"))
+            .or(self.last_modified.as_deref())
+    }
+}
+
+fn with_suffix(path: &Path, suffix: &str) -> PathBuf {
+    let mut path = path.as_os_str().to_owned();
+    path.push(suffix);
+    PathBuf::from(path)
+}
+
+/// The file keeping the validators of a cached file.
+fn http_meta_file(filename: &Path) -> PathBuf {
+    with_suffix(filename, ".http.json")
+}
+
+/// The first byte of a `Content-Range: bytes {start}-{end}/{total}` header.
+fn content_range_start(response: &Response) -> Option<u64> {
+    let value = response.headers().get(CONTENT_RANGE)?.to_str().ok()?;
+    let range = value.strip_prefix("bytes ")?;
+    range.split('-').next()?.parse().ok()
+}
+
+/// Build the http client with the timeouts, proxies and certificates of `config`.
+fn build_client(config: &Config) -> anyhow::Result<Client> {
+    let mut builder = Client::builder()
+        .connect_timeout(Duration::from_secs(config.connect_timeout()))
+        .read_timeout(Duration::from_secs(config.read_timeout()))
+        .user_agent(config.user_agent());
+    if !config.system_proxy() {
+        builder = builder.no_proxy();
+    }
+    if let Some(proxy) = &config.proxy {
+        let proxy = Proxy::all(proxy)
+            .map_err(|e| anyhow::anyhow!("Invalid proxy {}, err: {}", proxy, e))?
+            .no_proxy(NoProxy::from_string(&config.no_proxy().join(",")));
+        builder = builder.proxy(proxy);
+    }
+    for path in config.ca_certs() {
+        let data = std::fs::read(path)
+            .map_err(|e| anyhow::anyhow!("Read {}, err: {}", path.display(), e))?;
+        let certs = match Certificate::from_pem_bundle(&data) {
+            Ok(certs) if !certs.is_empty() => certs,
+            _ => vec![Certificate::from_der(&data)
+                .map_err(|e| anyhow::anyhow!("Read certificate {}, err: {}", path.display(), e))?],
+        };
+        for cert in certs {
+            builder = builder.add_root_certificate(cert);
+        }
+    }
+    builder
+        .build()
+        .map_err(|e| anyhow::anyhow!("Build the http client, err: {}", e))
+}
+
 #[derive(Debug)]
 pub struct Context {
     /// The configuration.
     pub config: Config,
-    /// The HTTP client.
-    pub client: Client,
+    /// The HTTP client, built on the first download so a bad network setting doesn't
+    /// stop the commands that don't need it, like `config`.
+    client: OnceLock<Client>,
     /// The caches.
     pub caches: CachesManager,
     /// The progress bar.
@@ -33,18 +173,24 @@ pub struct Context {
 
 impl Context {
     /// Create a new context.
-    pub async fn new() -> Self {
-        let config = Config::new_from_file();
-        let client = Client::new();
+    pub async fn new(config: Config) -> anyhow::Result<Self> {
         let caches = CachesManager::new(&config).await;
         let bar = ProgressBarManager::new(&config);
 
-        Self {
+        Ok(Self {
             config,
-            client,
+            client: OnceLock::new(),
             caches,
             bar,
+        })
+    }
+
+    fn client(&self) -> anyhow::Result<&Client> {
+        if let Some(client) = self.client.get() {
+            return Ok(client);
         }
+        let client = build_client(&self.config)?;
+        Ok(self.client.get_or_init(|| client))
     }
 
     pub async fn download_file<T, P, S>(
@@ -56,48 +202,253 @@ impl Context {
     ) -> anyhow::Result<T>
     where
         P: AsRef<Path>,
-        T: Serialize + DeserializeOwned,
-        S: IntoUrl,
+        T: DeserializeOwned,
+        S: AsRef<str>,
     {
-        pb.set_message(format!("Downloading {}", filename.as_ref().display()));
-        if skip_if_exists && self.cache_file_exists(filename.as_ref()) {
-            let value = self.read_from_cache(filename.as_ref()).await?;
-            Ok(value)
-        } else {
-            let response = self.client.get(url).send().await?;
-            let payload = self.download_with_progress(response, pb).await?;
-            let value: T = serde_json::from_slice(&payload).map_err(|e| {
-                anyhow::anyhow!("Parse {}, err: {}", filename.as_ref().to_string_lossy(), e)
-            })?;
-            self.write_to_cache(filename.as_ref(), &value)
-                .await
-                .map_err(|e| {
-                    anyhow::anyhow!("Write {}, err: {}", filename.as_ref().to_string_lossy(), e)
-                })?;
-            Ok(value)
+        let filename = filename.as_ref();
+        pb.set_message(format!("Downloading {}", filename.display()));
+        if !skip_if_exists || !self.cache_file_exists(filename) {
+            let url = Url::parse(url.as_ref())?;
+            self.download_to_cache(filename, &url, pb, None).await?;
+        }
+        match self.read_from_cache(filename).await {
+            Ok(value) => Ok(value),
+            Err(e) => {
+                // Never keep a broken file around, so the next run downloads it again.
+                let _ = tokio::fs::remove_file(self.build_cache_path(filename)).await;
+                let _ =
+                    tokio::fs::remove_file(self.build_cache_path(http_meta_file(filename))).await;
+                Err(e)
+            }
         }
     }
 
-    pub async fn download_with_progress(
+    /// Download an `http(s)://` or `file://` url into the cache.
+    ///
+    /// If a `sink` is given, the whole body is also sent to it while downloading, and
+    /// `true` is returned. Nothing is sent if the cached file is still fresh or the url
+    /// is a local file, then `false` is returned and the cached file should be read instead.
+    pub async fn download_to_cache(
         &self,
-        response: Response,
+        filename: &Path,
+        url: &Url,
         pb: &Arc<ProgressBar>,
-    ) -> anyhow::Result<Bytes> {
-        let total_size = response.content_length();
+        sink: Option<Sender<Bytes>>,
+    ) -> anyhow::Result<bool> {
+        match url.scheme() {
+            "http" | "https" if self.config.offline() => {
+                bail!("Can not download {} in offline mode", url)
+            }
+            "http" | "https" => {
+                let retries = self.config.retries();
+                let mut sent = 0;
+                let mut attempt = 0;
+                loop {
+                    match self
+                        .download_http(filename, url, pb, sink.as_ref(), &mut sent)
+                        .await
+                    {
+                        Err(e) if attempt < retries && is_retryable(&e) => {
+                            attempt += 1;
+                            let delay = backoff(attempt);
+                            pb.set_message(format!(
+                                "Retrying {} in {:.1}s ({}/{}), err: {}",
+                                filename.display(),
+                                delay.as_secs_f64(),
+                                attempt,
+                                retries,
+                                e
+                            ));
+                            tokio::time::sleep(delay).await;
+                            pb.set_message(format!(
+                                "Downloading {} (retry {}/{})",
+                                filename.display(),
+                                attempt,
+                                retries
+                            ));
+                        }
+                        ret => return ret,
+                    }
+                }
+            }
+            "file" => {
+                let path = url
+                    .to_file_path()
+                    .map_err(|_| anyhow::anyhow!("Invalid file url: {}", url))?;
+                let target = self.build_cache_path(filename);
+                let part = with_suffix(&target, ".part");
+                if let Some(parent) = target.parent() {
+                    tokio::fs::create_dir_all(parent).await?;
+                }
+                let size = tokio::fs::copy(&path, &part)
+                    .await
+                    .map_err(|e| anyhow::anyhow!("Read {}, err: {}", path.display(), e))?;
+                tokio::fs::rename(&part, &target).await?;
+                pb.update_template(Some(size));
+                pb.set_position(size);
+                Ok(false)
+            }
+            scheme => bail!("Unsupported url scheme: {}", scheme),
+        }
+    }
+
+    async fn read_http_meta(&self, filename: &Path, url: &Url) -> Option<HttpMeta> {
+        let meta: HttpMeta = self.read_from_cache(filename).await.ok()?;
+        (meta.url == url.as_str()).then_some(meta)
+    }
+
+    /// Download an http url into the cache.
+    ///
+    /// The response is streamed into `{filename}.part`, which is moved into place once
+    /// complete. An interrupted download is resumed from the partial file with a range
+    /// request, and a cached file is revalidated with its `ETag` and `Last-Modified`.
+    ///
+    /// `sent` is the number of bytes already sent to `sink` by a previous attempt, which are
+    /// not sent again.
+    async fn download_http(
+        &self,
+        filename: &Path,
+        url: &Url,
+        pb: &Arc<ProgressBar>,
+        sink: Option<&Sender<Bytes>>,
+        sent: &mut u64,
+    ) -> anyhow::Result<bool> {
+        let target = self.build_cache_path(filename);
+        let part = with_suffix(&target, ".part");
+        let meta_file = http_meta_file(filename);
+        let part_meta_file = http_meta_file(&with_suffix(filename, ".part"));
+        if let Some(parent) = target.parent() {
+            tokio::fs::create_dir_all(parent).await?;
+        }
+
+        let mut response = None;
+        // Try twice, in case the partial file can not be resumed.
+        for _ in 0..2 {
+            let mut request = self.client()?.get(url.clone());
+            let part_meta = self.read_http_meta(&part_meta_file, url).await;
+            let offset = match tokio::fs::metadata(&part).await {
+                Ok(metadata) => metadata.len(),
+                Err(_) => 0,
+            };
+            if let (Some(validator), true) =
+                (part_meta.as_ref().and_then(HttpMeta::if_range), offset > 0)
+            {
+                request = request
+                    .header(RANGE, format!("bytes={}-", offset))
+                    .header(IF_RANGE, validator);
+            } else if target.exists() {
+                if let Some(meta) = self.read_http_meta(&meta_file, url).await {
+                    if let Some(etag) = &meta.etag {
+                        request = request.header(IF_NONE_MATCH, etag);
+                    }
+                    if let Some(last_modified) = &meta.last_modified {
+                        request = request.header(IF_MODIFIED_SINCE, last_modified);
+                    }
+                }
+            }
+
+            let resp = request.send().await?;
+            match resp.status() {
+                StatusCode::NOT_MODIFIED => {
+                    pb.set_message(format!("{} not modified", filename.display()));
+                    return Ok(false);
+                }
+                StatusCode::PARTIAL_CONTENT if content_range_start(&resp) == Some(offset) => {
+                    response = Some((resp, offset));
+                    break;
+                }
+                // The partial file can not be resumed from, so start over without a range.
+                StatusCode::RANGE_NOT_SATISFIABLE | StatusCode::PARTIAL_CONTENT => {
+                    let _ = tokio::fs::remove_file(&part).await;
+                    let _ = tokio::fs::remove_file(self.build_cache_path(&part_meta_file)).await;
+                }
+                StatusCode::OK => {
+                    self.write_to_cache(&part_meta_file, &HttpMeta::new(url, &resp))
+                        .await?;
+                    response = Some((resp, 0));
+                    break;
+                }
+                status => {
+                    resp.error_for_status()?;
+                    bail!("Unexpected status {} for {}", status, url);
+                }
+            }
+        }
+        let Some((response, offset)) = response else {
+            bail!("Failed to resume the download of {}", url);
+        };
+
+        let total_size = response.content_length().map(|len| offset + len);
         pb.update_template(total_size);
+        pb.set_position(offset);
 
-        let mut downloaded: u64 = 0;
-        let mut stream = response.bytes_stream();
-        let mut payload = BytesMut::new();
+        if let (Some(sink), true) = (sink, offset > *sent) {
+            // Replay what has been downloaded before, but not sent yet.
+            let mut file = tokio::fs::File::open(&part).await?;
+            let mut buf = vec![0; 64 * 1024];
+            let mut position = 0;
+            loop {
+                let n = file.read(&mut buf).await?;
+                if n == 0 {
+                    break;
+                }
+                let chunk = Bytes::copy_from_slice(&buf[..n]);
+                if send_new_bytes(sink, chunk, position, sent).await.is_err() {
+                    bail!(SinkClosed(url.to_string()));
+                }
+                position += n as u64;
+            }
+        }
 
+        let file = tokio::fs::OpenOptions::new()
+            .create(true)
+            .write(true)
+            .append(offset > 0)
+            .truncate(offset == 0)
+            .open(&part)
+            .await?;
+        let mut writer = BufWriter::new(file);
+        let mut downloaded = offset;
+        let mut stream = response.bytes_stream();
         while let Some(chunk) = stream.next().await {
-            let chunk = chunk?;
+            let chunk = match chunk {
+                Ok(chunk) => chunk,
+                Err(e) => {
+                    // Keep what we have got so far, to resume from it next time.
+                    writer.flush().await?;
+                    return Err(e.into());
+                }
+            };
+            writer.write_all(&chunk).await?;
+            let position = downloaded;
             downloaded += chunk.len() as u64;
-            payload.extend_from_slice(&chunk);
+            if let Some(sink) = sink {
+                if send_new_bytes(sink, chunk, position, sent).await.is_err() {
+                    writer.flush().await?;
+                    bail!(SinkClosed(url.to_string()));
+                }
+            }
             pb.set_position(downloaded);
         }
+        writer.flush().await?;
 
-        Ok(payload.freeze())
+        if let Some(total_size) = total_size {
+            if downloaded != total_size {
+                bail!(IncompleteDownload {
+                    url: url.to_string(),
+                    got: downloaded,
+                    total: total_size,
+                });
+            }
+        }
+
+        tokio::fs::rename(&part, &target).await?;
+        tokio::fs::rename(
+            self.build_cache_path(&part_meta_file),
+            self.build_cache_path(&meta_file),
+        )
+        .await?;
+        Ok(sink.is_some())
     }
 
     pub fn cache_file_exists<F>(&self, filename: F) -> bool
@@ -136,11 +487,10 @@ impl Context {
         F: AsRef<Path>,
     {
         let cache_path = self.config.cache_dir().join(filename);
-        if let Some(parent) = cache_path.parent() {
-            tokio::fs::create_dir_all(parent).await?;
-        }
+        // Create the temporary file next to the target, so it can be renamed into place.
+        let tmp_dir = cache_path.parent().unwrap_or(self.config.cache_dir());
+        tokio::fs::create_dir_all(tmp_dir).await?;
 
-        let tmp_dir = env::temp_dir();
         let ptr = match CString::new(format!("{}/dq-cache-XXXXXX.cache", tmp_dir.display())) {
             Ok(p) => p.into_raw(),
             Err(e) => bail!(e),
@@ -0,0 +1,269 @@
+use std::{ops::Range, path::Path, time::SystemTime};
+
+use memmap2::Mmap;
+
+use crate::utils::io::{has_header, map_file, read_u32, read_u64};
+
+use super::{Index, IndexEntry};
+
+/// The on-disk layout, all integers are little-endian:
+///
+/// ```text
+/// magic       b"DQIX"
+/// version     u32
+/// source_len  u64     length of the `index.json` this file was built from
+/// source_time u64     mtime of that `index.json`, in nanoseconds
+/// count       u32
+/// records     count * [name_off, name_len, path_off, path_len, type_off, type_len]: u32
+/// strings     the utf-8 blob the records point into
+/// ```
+///
+/// Records are sorted by name. A `type_len` of `u32::MAX` means the entry has no type.
+const MAGIC: &[u8; 4] = b"DQIX";
+const VERSION: u32 = 1;
+const HEADER_LEN: usize = 4 + 4 + 8 + 8 + 4;
+const RECORD_LEN: usize = 6 * 4;
+const NO_TYPE: u32 = u32::MAX;
+
+enum Data {
+    Mapped(Mmap),
+    Owned(Vec<u8>),
+}
+
+impl AsRef<[u8]> for Data {
+    fn as_ref(&self) -> &[u8] {
+        match self {
+            Data::Mapped(mmap) => mmap,
+            Data::Owned(vec) => vec,
+        }
+    }
+}
+
+/// A compact, memory-mappable name table built from an [`Index`].
+pub struct CompactIndex {
+    data: Data,
+    count: usize,
+}
+
+/// An entry borrowed from a [`CompactIndex`].
+#[derive(Debug, Clone, Copy)]
+pub struct CompactEntry<'a> {
+    pub name: &'a str,
+    pub path: &'a str,
+    pub r#type: Option<&'a str>,
+}
+
+/// Identify the `index.json` a compact index was built from.
+pub(super) fn source_stamp(source: &Path) -> anyhow::Result<(u64, u64)> {
+    let metadata = std::fs::metadata(source)?;
+    let mtime = metadata
+        .modified()?
+        .duration_since(SystemTime::UNIX_EPOCH)?
+        .as_nanos() as u64;
+    Ok((metadata.len(), mtime))
+}
+
+impl CompactIndex {
+    /// Encode an index, stamped with the `(len, mtime)` of its source file.
+    fn encode(index: &Index, stamp: (u64, u64)) -> Vec<u8> {
+        let mut entries = index.entries.iter().collect::<Vec<_>>();
+        entries.sort_by(|a, b| a.name.cmp(&b.name));
+
+        let mut records = Vec::with_capacity(entries.len() * RECORD_LEN);
+        let mut strings = Vec::new();
+        let mut push = |s: &str, records: &mut Vec<u8>| {
+            records.extend_from_slice(&(strings.len() as u32).to_le_bytes());
+            records.extend_from_slice(&(s.len() as u32).to_le_bytes());
+            strings.extend_from_slice(s.as_bytes());
+        };
+        for entry in entries.iter() {
+            push(&entry.name, &mut records);
+            push(&entry.path, &mut records);
+            match &entry.r#type {
+                Some(t) => push(t, &mut records),
+                None => {
+                    records.extend_from_slice(&0u32.to_le_bytes());
+                    records.extend_from_slice(&NO_TYPE.to_le_bytes());
+                }
+            }
+        }
+
+        let mut data = Vec::with_capacity(HEADER_LEN + records.len() + strings.len());
+        data.extend_from_slice(MAGIC);
+        data.extend_from_slice(&VERSION.to_le_bytes());
+        data.extend_from_slice(&stamp.0.to_le_bytes());
+        data.extend_from_slice(&stamp.1.to_le_bytes());
+        data.extend_from_slice(&(entries.len() as u32).to_le_bytes());
+        data.extend_from_slice(&records);
+        data.extend_from_slice(&strings);
+        data
+    }
+
+    /// Build the compact index of `index`, which was read from `source`, into `target`.
+    pub fn build(index: &Index, source: &Path, target: &Path) -> anyhow::Result<()> {
+        let data = Self::encode(index, source_stamp(source)?);
+        let tmp = target.with_extension("bin.tmp");
+        std::fs::write(&tmp, data)?;
+        std::fs::rename(tmp, target)?;
+        Ok(())
+    }
+
+    /// Build a compact index in memory, without touching the disk.
+    pub fn from_index(index: &Index) -> Self {
+        let data = Data::Owned(Self::encode(index, (0, 0)));
+        Self::validate(data).unwrap()
+    }
+
+    /// Map the compact index at `path`. `None` tells to build it again: it can't be
+    /// read, fails [`Self::validate`], or its stamp is not the one of `source` now.
+    pub fn open(path: &Path, source: &Path) -> Option<Self> {
+        let index = Self::validate(Data::Mapped(map_file(path).ok()?))?;
+        let stamp = source_stamp(source).ok()?;
+        let data = index.data.as_ref();
+        if (read_u64(data, 8), read_u64(data, 16)) != stamp {
+            return None;
+        }
+        Some(index)
+    }
+
+    /// Check the header, and that every string of every record is in the file.
+    fn validate(data: Data) -> Option<Self> {
+        let bytes = data.as_ref();
+        if !has_header(bytes, MAGIC, VERSION, HEADER_LEN) {
+            return None;
+        }
+        let count = read_u32(bytes, 24) as usize;
+        let strings_len = bytes
+            .len()
+            .checked_sub(HEADER_LEN + count.checked_mul(RECORD_LEN)?)?;
+        let index = Self { data, count };
+        for i in 0..count {
+            for field in 0..3 {
+                let (offset, len) = index.field(i, field);
+                if len != NO_TYPE && offset as usize + len as usize > strings_len {
+                    return None;
+                }
+            }
+        }
+        Some(index)
+    }
+
+    fn field(&self, i: usize, field: usize) -> (u32, u32) {
+        let offset = HEADER_LEN + i * RECORD_LEN + field * 8;
+        let data = self.data.as_ref();
+        (read_u32(data, offset), read_u32(data, offset + 4))
+    }
+
+    fn string(&self, range: Range<usize>) -> &str {
+        let base = HEADER_LEN + self.count * RECORD_LEN;
+        let data = &self.data.as_ref()[base + range.start..base + range.end];
+        std::str::from_utf8(data).unwrap_or_default()
+    }
+
+    fn string_field(&self, i: usize, field: usize) -> Option<&str> {
+        let (offset, len) = self.field(i, field);
+        if len == NO_TYPE {
+            return None;
+        }
+        Some(self.string(offset as usize..offset as usize + len as usize))
+    }
+
+    pub fn get(&self, i: usize) -> CompactEntry<'_> {
+        CompactEntry {
+            name: self.string_field(i, 0).unwrap_or_default(),
+            path: self.string_field(i, 1).unwrap_or_default(),
+            r#type: self.string_field(i, 2),
+        }
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = CompactEntry<'_>> {
+        (0..self.count).map(|i| self.get(i))
+    }
+}
+
+impl CompactEntry<'_> {
+    pub fn to_entry(self) -> IndexEntry {
+        IndexEntry {
+            name: self.name.to_string(),
+            path: self.path.to_string(),
+            r#type: self.r#type.map(str::to_string),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::entity::IndexType;
+
+    fn index() -> Index {
+        let entry = |name: &str, path: &str, r#type: Option<&str>| IndexEntry {
+            name: name.to_string(),
+            path: path.to_string(),
+            r#type: r#type.map(str::to_string),
+        };
+        Index {
+            entries: vec![
+                entry("push", "array/push", Some("Array")),
+                entry("Array", "array", Some("Array")),
+                entry("globalThis", "global_this", None),
+            ],
+            types: vec![IndexType {
+                name: "Array".to_string(),
+                count: 2,
+                slug: "array".to_string(),
+            }],
+        }
+    }
+
+    /// Build the compact index of [`index`] in `dir`, returning its path and the source.
+    fn build(dir: &Path) -> (std::path::PathBuf, std::path::PathBuf) {
+        let source = dir.join("index.json");
+        let target = dir.join("index.bin");
+        std::fs::write(&source, serde_json::to_string(&index()).unwrap()).unwrap();
+        CompactIndex::build(&index(), &source, &target).unwrap();
+        (target, source)
+    }
+
+    #[test]
+    fn round_trip() {
+        let dir = tempfile::tempdir().unwrap();
+        let (target, source) = build(dir.path());
+        let compact = CompactIndex::open(&target, &source).unwrap();
+        let entries = compact
+            .iter()
+            .map(|entry| (entry.name, entry.path, entry.r#type))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            entries,
+            [
+                ("Array", "array", Some("Array")),
+                ("globalThis", "global_this", None),
+                ("push", "array/push", Some("Array")),
+            ]
+        );
+        assert_eq!(
+            CompactIndex::from_index(&index()).iter().count(),
+            entries.len()
+        );
+    }
+
+    #[test]
+    fn rejects_a_changed_source() {
+        let dir = tempfile::tempdir().unwrap();
+        let (target, source) = build(dir.path());
+        std::fs::write(&source, "{}").unwrap();
+        assert!(CompactIndex::open(&target, &source).is_none());
+    }
+
+    #[test]
+    fn rejects_a_truncated_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let (target, source) = build(dir.path());
+        let data = std::fs::read(&target).unwrap();
+        for len in [0, 3, HEADER_LEN - 1, HEADER_LEN, data.len() - 1] {
+            std::fs::write(&target, &data[..len]).unwrap();
+            assert!(CompactIndex::open(&target, &source).is_none(), "{}", len);
+        }
+    }
+}
@@ -1,13 +1,26 @@
-use std::{collections::HashMap, path::Path, sync::Arc};
+use std::{
+    cmp::Ordering,
+    collections::HashMap,
+    fmt,
+    io::{BufReader, Read},
+    path::Path,
+    sync::Arc,
+};
 
-use futures::{stream::FuturesUnordered, StreamExt};
-use serde::{Deserialize, Serialize};
+use reqwest::Url;
+use serde::{
+    de::{Error as _, MapAccess, Visitor},
+    Deserialize, Deserializer as _, Serialize,
+};
 
-use crate::{context::Context, utils::progress::ProgressBar};
+use crate::{
+    context::{is_retryable, Context, SinkClosed},
+    html,
+    store::{self, PageStore, PageWriter, Storage},
+    utils::{io::ChannelReader, progress::ProgressBar},
+};
 
-use super::Index;
-
-const DEVDOCS_META_URL: &str = "https://devdocs.io/docs.json";
+use super::{split_fragment, CompactIndex, FullTextIndex, Index};
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct Docset {
@@ -21,37 +34,273 @@ pub struct Docset {
     pub db_size: i64,
 }
 
+/// A docset resolved from what the user typed.
+#[derive(Debug, Clone, Copy)]
+pub struct Resolved<'a> {
+    pub docset: &'a Docset,
+    /// Whether it was picked among several versions, rather than named by its exact slug.
+    pub fallback: bool,
+}
+
+/// Whether a docset has been downloaded into the cache.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum InstallStatus {
+    /// No version of the docset is in the cache.
+    NotInstalled,
+    /// The version listed in `docsets.json` is in the cache.
+    Installed,
+    /// Only older versions of the docset are in the cache.
+    Outdated,
+}
+
 impl Docset {
     /// Try to update all docsets if outdated, then return them.
+    ///
+    /// In offline mode, or if the server can't be reached, the cached docsets are used
+    /// even if they are outdated.
     pub async fn try_to_fetch_docsets(context: &mut Context) -> anyhow::Result<Vec<Docset>> {
-        if context.cache_file_exists("docsets.json") && !context.caches.should_refresh_cache() {
+        let cached = context.cache_file_exists("docsets.json");
+        if cached && (context.config.offline() || !context.caches.should_refresh_cache()) {
             return context.read_from_cache("docsets.json").await;
         }
+        if context.config.offline() {
+            anyhow::bail!("docsets.json is not cached, run `dq update` without `--offline` first");
+        }
         let pb = context.bar.add_root();
-        let ret = context
-            .download_file("docsets.json", DEVDOCS_META_URL, &pb, false)
-            .await?;
+        let ret = match context
+            .download_file("docsets.json", context.config.meta_url(), &pb, false)
+            .await
+        {
+            Ok(ret) => ret,
+            Err(e) if cached && is_retryable(&e) => {
+                pb.finish("docsets.json not refreshed");
+                eprintln!("warning: using the cached docsets.json, err: {}", e);
+                return context.read_from_cache("docsets.json").await;
+            }
+            Err(e) => return Err(e),
+        };
         context.caches.flush_meta().await?;
         pb.finish("docsets.json downloaded");
         Ok(ret)
     }
 
+    /// Find the docset with the given slug.
+    pub fn find<'a>(docsets: &'a [Docset], slug: &str) -> anyhow::Result<&'a Docset> {
+        docsets
+            .iter()
+            .find(|docset| docset.slug == slug)
+            .ok_or_else(|| anyhow::anyhow!("docset {} not found", slug))
+    }
+
+    /// Resolve a docset from user input: an exact slug, a bare name like `python`, or a
+    /// name with a version like `python@3.11`. Without an exact match, the newest installed
+    /// version wins, then the newest available one.
+    pub fn resolve<'a>(
+        context: &Context,
+        docsets: &'a [Docset],
+        query: &str,
+    ) -> anyhow::Result<Resolved<'a>> {
+        if let Ok(docset) = Self::find(docsets, query) {
+            return Ok(Resolved {
+                docset,
+                fallback: false,
+            });
+        }
+        let (name, version) = match query.split_once('@') {
+            Some((name, version)) if !version.is_empty() => (name, Some(version)),
+            Some((name, _)) => (name, None),
+            None => (query, None),
+        };
+        let candidates = docsets
+            .iter()
+            .filter(|docset| docset.name_slug() == name)
+            .filter(|docset| version.is_none_or(|version| docset.has_version(version)))
+            .collect::<Vec<_>>();
+        let newest = |docsets: &mut dyn Iterator<Item = &&'a Docset>| {
+            docsets
+                .copied()
+                .max_by(|a, b| compare_versions(a.version_key(), b.version_key()))
+        };
+
+        let docset = newest(
+            &mut candidates
+                .iter()
+                .filter(|docset| docset.install_status(context) == InstallStatus::Installed),
+        )
+        .or_else(|| newest(&mut candidates.iter()))
+        .ok_or_else(|| anyhow::anyhow!("docset {} not found", query))?;
+        Ok(Resolved {
+            docset,
+            fallback: true,
+        })
+    }
+
+    /// The slug without its version, `python` for `python~3.12`.
+    fn name_slug(&self) -> &str {
+        self.slug.split('~').next().unwrap_or_default()
+    }
+
+    /// The version to sort by, the most precise one known.
+    fn version_key(&self) -> &str {
+        self.release
+            .as_deref()
+            .or(self.version.as_deref())
+            .or_else(|| self.slug.split_once('~').map(|(_, version)| version))
+            .unwrap_or_default()
+    }
+
+    /// Whether this docset is `version`, or a release of it, so `3` matches `3.12`.
+    fn has_version(&self, version: &str) -> bool {
+        let matches = |v: &str| {
+            v == version
+                || v.strip_prefix(version)
+                    .is_some_and(|rest| rest.starts_with(['.', '-', ' ']))
+        };
+        [
+            self.version.as_deref(),
+            self.release.as_deref(),
+            self.slug.split_once('~').map(|(_, version)| version),
+        ]
+        .into_iter()
+        .flatten()
+        .any(matches)
+    }
+
     pub fn base_directory(&self) -> String {
         format!("{}/{}", self.slug, self.mtime)
     }
 
+    /// Build the url of a file of this docset, e.g. `index.json`.
+    fn document_url(&self, context: &Context, filename: &str) -> String {
+        format!(
+            "{}/{}/{}?{}",
+            context.config.documents_url(),
+            self.slug,
+            filename,
+            self.mtime
+        )
+    }
+
+    /// Read the index of this docset from the cache.
+    pub async fn read_index(&self, context: &Context) -> anyhow::Result<Index> {
+        self.ensure_installed(context)?;
+        context
+            .read_from_cache(self.base_directory() + "/index.json")
+            .await
+    }
+
+    fn ensure_installed(&self, context: &Context) -> anyhow::Result<()> {
+        if !context.cache_file_exists(self.base_directory() + "/index.json") {
+            anyhow::bail!(
+                "docset {} is not installed, run `dq update {}` first",
+                self.slug,
+                self.slug
+            );
+        }
+        Ok(())
+    }
+
+    /// Open the pages of this docset, in whichever storage they are.
+    pub fn page_store(&self, context: &Context) -> anyhow::Result<Box<dyn PageStore>> {
+        store::open(&context.build_cache_path(self.base_directory()))
+    }
+
+    /// Read a page of this docset from the cache. `path` must not contain a fragment.
+    pub async fn read_page(&self, context: &Context, path: &str) -> anyhow::Result<String> {
+        self.ensure_installed(context)?;
+        self.page_store(context)?
+            .read(path)
+            .map_err(|e| anyhow::anyhow!("Read page {} of {}, err: {}", path, self.slug, e))?
+            .ok_or_else(|| anyhow::anyhow!("page {} not found in {}", path, self.slug))
+    }
+
+    /// Read the html of an entry. If `path` has a fragment, only the section it points
+    /// to is returned, or the whole page if there is no such section.
+    pub async fn read_entry(&self, context: &Context, path: &str) -> anyhow::Result<String> {
+        let (page, fragment) = split_fragment(path);
+        let content = self.read_page(context, page).await?;
+        Ok(fragment
+            .and_then(|id| html::section_by_id(&content, id))
+            .unwrap_or(content))
+    }
+
+    /// Read the compact index of this docset, rebuilding it from `index.json` if it's
+    /// missing or stale.
+    pub async fn read_compact_index(&self, context: &Context) -> anyhow::Result<CompactIndex> {
+        let source = context.build_cache_path(self.base_directory() + "/index.json");
+        let target = context.build_cache_path(self.base_directory() + "/index.bin");
+        if let Some(compact) = CompactIndex::open(&target, &source) {
+            return Ok(compact);
+        }
+        let index = self.read_index(context).await?;
+        // The cache may be read-only, so failing to rebuild is not an error.
+        let _ = CompactIndex::build(&index, &source, &target);
+        Ok(CompactIndex::from_index(&index))
+    }
+
+    /// Build the full-text index of this docset from its pages, returning the number of
+    /// pages indexed.
+    pub async fn build_fulltext_index(&self, context: &Context) -> anyhow::Result<u64> {
+        let base_directory = context.build_cache_path(self.base_directory());
+        tokio::task::spawn_blocking(move || {
+            let store = store::open(&base_directory)?;
+            FullTextIndex::build(
+                store.as_ref(),
+                &base_directory.join("db.json"),
+                &base_directory.join("fulltext.bin"),
+            )
+        })
+        .await?
+    }
+
+    /// Read the full-text index of this docset, building it first if it's missing or
+    /// stale.
+    pub async fn read_fulltext_index(&self, context: &Context) -> anyhow::Result<FullTextIndex> {
+        self.ensure_installed(context)?;
+        let source = context.build_cache_path(self.base_directory() + "/db.json");
+        let target = context.build_cache_path(self.base_directory() + "/fulltext.bin");
+        if let Some(index) = FullTextIndex::open(&target, &source) {
+            return Ok(index);
+        }
+        self.build_fulltext_index(context).await?;
+        FullTextIndex::open(&target, &source)
+            .ok_or_else(|| anyhow::anyhow!("Read {}, err: corrupted", target.display()))
+    }
+
+    /// List the `mtime`s of every version of this docset found in the cache.
+    pub fn installed_mtimes(&self, context: &Context) -> Vec<i64> {
+        let Ok(dir) = std::fs::read_dir(context.build_cache_path(&self.slug)) else {
+            return vec![];
+        };
+        dir.filter_map(|entry| entry.ok())
+            .filter(|entry| entry.path().join("index.json").exists())
+            .filter_map(|entry| entry.file_name().to_str()?.parse().ok())
+            .collect()
+    }
+
+    pub fn install_status(&self, context: &Context) -> InstallStatus {
+        let mtimes = self.installed_mtimes(context);
+        if mtimes.contains(&self.mtime) {
+            InstallStatus::Installed
+        } else if mtimes.is_empty() {
+            InstallStatus::NotInstalled
+        } else {
+            InstallStatus::Outdated
+        }
+    }
+
     async fn fetch_index(
         &self,
         context: &Context,
         parent: &Arc<ProgressBar>,
     ) -> anyhow::Result<Index> {
-        let url = format!(
-            "https://documents.devdocs.io/{}/index.json?{}",
-            self.slug, self.mtime
-        );
+        let url = self.document_url(context, "index.json");
         let filename = self.base_directory() + "/index.json";
         let pb = context.bar.add_child_with_total(parent, None);
-        let index: Index = context.download_file(filename, url, &pb, true).await?;
+        let index: Index = context
+            .download_file(filename, url, &pb, !context.config.force())
+            .await?;
         pb.finish(format!(
             "{} index downloaded, got {} entries",
             self.name,
@@ -61,70 +310,112 @@ impl Docset {
         Ok(index)
     }
 
-    async fn fetch_db(
-        &self,
-        context: &Context,
-        parent: &Arc<ProgressBar>,
-    ) -> anyhow::Result<HashMap<String, String>> {
-        let url = format!(
-            "https://documents.devdocs.io/{}/db.json?{}",
-            self.slug, self.mtime
-        );
+    /// Download `db.json`, unpacking its pages while it's being downloaded.
+    async fn fetch_db(&self, context: &Context, parent: &Arc<ProgressBar>) -> anyhow::Result<()> {
         let filename = self.base_directory() + "/db.json";
+        if !context.config.force() && context.cache_file_exists(&filename) {
+            return self.unpack_cached_db_if_changed(context, parent).await;
+        }
+
+        let url = Url::parse(&self.document_url(context, "db.json"))?;
         let pb = context.bar.add_child_with_total(parent, None);
-        let db = context.download_file(filename, url, &pb, true).await?;
+        pb.set_message(format!("Downloading {}", filename));
+        let (tx, rx) = tokio::sync::mpsc::channel(16);
+        let (downloaded, unpacked) = tokio::join!(
+            context.download_to_cache(Path::new(&filename), &url, &pb, Some(tx)),
+            self.unpack_db(context, parent, ChannelReader::new(rx)),
+        );
         pb.finish(format!("{} db downloaded", self.name));
         context.bar.remove_bar(&pb);
-        Ok(db)
-    }
 
-    async fn write_page(path: impl AsRef<Path>, data: &str) -> anyhow::Result<()> {
-        if let Some(parent) = path.as_ref().parent() {
-            tokio::fs::create_dir_all(parent).await?;
+        match downloaded {
+            // The unpacking failed first, which is the real cause.
+            Err(e) if e.is::<SinkClosed>() => Err(unpacked.err().unwrap_or(e)),
+            Err(e) => Err(e),
+            Ok(true) => {
+                if unpacked.is_err() {
+                    let _ = tokio::fs::remove_file(context.build_cache_path(&filename)).await;
+                }
+                unpacked.map(|_| ())
+            }
+            // Nothing was streamed, the cached file is up to date.
+            Ok(false) => self.unpack_cached_db_if_changed(context, parent).await,
         }
-        tokio::fs::write(path, data.as_bytes()).await?;
-        Ok(())
     }
 
-    async fn unpack_db(
+    /// Unpack the cached `db.json`, unless its pages are already there. The full-text
+    /// index is built right after unpacking, so it's up to date with `db.json` only if the
+    /// pages are. The pages are still moved if `--storage` asks for another storage.
+    async fn unpack_cached_db_if_changed(
         &self,
         context: &Context,
         parent: &Arc<ProgressBar>,
-        db: &HashMap<String, String>,
     ) -> anyhow::Result<()> {
-        let pb = context
-            .bar
-            .add_child_with_total(parent, Some(db.len() as u64));
-        let db_base_directory = context
-            .config
-            .cache_dir()
-            .join(self.base_directory())
-            .join("db");
-
-        let mut items = db.iter();
-        let mut futures = FuturesUnordered::new();
-
-        loop {
-            while futures.len() < 4 {
-                if let Some((path, content)) = items.next() {
-                    let filename = db_base_directory.join(path).join("_index");
-                    let fut = Self::write_page(filename, content);
-                    futures.push(fut);
-                } else {
-                    break;
-                }
-            }
-            if futures.is_empty() {
-                break;
-            }
-            if let Some(res) = futures.next().await {
-                res?;
-                pb.inc(1);
-            }
+        let base_directory = context.build_cache_path(self.base_directory());
+        let fulltext = base_directory.join("fulltext.bin");
+        if FullTextIndex::open(&fulltext, &base_directory.join("db.json")).is_none() {
+            self.unpack_cached_db(context, parent).await?;
+            return Ok(());
         }
+        let storage = self.storage(context);
+        tokio::task::spawn_blocking(move || store::migrate(&base_directory, storage)).await??;
+        Ok(())
+    }
 
-        pb.finish(format!("{} pages written", db.len()));
+    async fn unpack_cached_db(
+        &self,
+        context: &Context,
+        parent: &Arc<ProgressBar>,
+    ) -> anyhow::Result<u64> {
+        let filename = context.build_cache_path(self.base_directory() + "/db.json");
+        let file = std::fs::File::open(&filename)
+            .map_err(|e| anyhow::anyhow!("Read {}, err: {}", filename.display(), e))?;
+        self.unpack_db(context, parent, file).await
+    }
+
+    /// The storage of the pages: the one set with `--storage` or in the config, or else the
+    /// one the docset already uses, so a plain `update` doesn't undo a `migrate`.
+    fn storage(&self, context: &Context) -> Storage {
+        context.config.storage.unwrap_or_else(|| {
+            store::current_storage(&context.build_cache_path(self.base_directory()))
+        })
+    }
+
+    /// Unpack the pages of a `db.json` read from `reader`, in a blocking thread.
+    async fn unpack_db<R>(
+        &self,
+        context: &Context,
+        parent: &Arc<ProgressBar>,
+        reader: R,
+    ) -> anyhow::Result<u64>
+    where
+        R: Read + Send + 'static,
+    {
+        let pb = context.bar.add_msg(Some(parent), false);
+        let writer = store::create(
+            &context.build_cache_path(self.base_directory()),
+            self.storage(context),
+        )?;
+
+        let bar = pb.clone();
+        let count = tokio::task::spawn_blocking(move || unpack_pages(reader, writer, &bar)).await?;
+
+        if let Ok(count) = &count {
+            pb.finish(format!("{} pages written", count));
+        }
         context.bar.remove_bar(&pb);
+        count
+    }
+
+    /// Unpack the pages again, from the cached `db.json` if it's intact, or from a fresh
+    /// download otherwise.
+    pub async fn repair(&self, context: &Context, parent: &Arc<ProgressBar>) -> anyhow::Result<()> {
+        if self.unpack_cached_db(context, parent).await.is_err() {
+            let filename = context.build_cache_path(self.base_directory() + "/db.json");
+            let _ = tokio::fs::remove_file(filename).await;
+            self.fetch_db(context, parent).await?;
+        }
+        self.build_fulltext_index(context).await?;
         Ok(())
     }
 
@@ -138,9 +429,93 @@ impl Docset {
 
         let (index, db) = tokio::join!(self.fetch_index(context, &pb), self.fetch_db(context, &pb));
         let index = index?;
-        let db = db?;
-        self.unpack_db(context, &pb, &db).await?;
+        db?;
+        let source = context.build_cache_path(self.base_directory() + "/index.json");
+        let target = context.build_cache_path(self.base_directory() + "/index.bin");
+        if CompactIndex::open(&target, &source).is_none() {
+            CompactIndex::build(&index, &source, &target)?;
+        }
+        let source = context.build_cache_path(self.base_directory() + "/db.json");
+        let target = context.build_cache_path(self.base_directory() + "/fulltext.bin");
+        if FullTextIndex::open(&target, &source).is_none() {
+            pb.set_message(format!("Indexing the pages of {}", self.name));
+            self.build_fulltext_index(context).await?;
+        }
 
         Ok(index)
     }
 }
+
+/// Compare versions like `3.9` and `3.12` part by part, numerically where possible.
+fn compare_versions(a: &str, b: &str) -> Ordering {
+    let parts = |v: &'_ str| {
+        v.split(|c: char| !c.is_ascii_alphanumeric())
+            .filter(|part| !part.is_empty())
+            .map(|part| (part.parse::<u64>().ok(), part.to_string()))
+            .collect::<Vec<_>>()
+    };
+    let (a, b) = (parts(a), parts(b));
+    for (a, b) in a.iter().zip(b.iter()) {
+        let ordering = match (a, b) {
+            ((Some(x), _), (Some(y), _)) => x.cmp(y),
+            // A number is newer than a tag like `beta`.
+            ((Some(_), _), (None, _)) => Ordering::Greater,
+            ((None, _), (Some(_), _)) => Ordering::Less,
+            ((None, x), (None, y)) => x.cmp(y),
+        };
+        if ordering != Ordering::Equal {
+            return ordering;
+        }
+    }
+    a.len().cmp(&b.len())
+}
+
+/// Visit the pages of a `db.json` one by one, without keeping them in memory.
+struct PagesVisitor<F>(F);
+
+impl<'de, F> Visitor<'de> for PagesVisitor<F>
+where
+    F: FnMut(String, String) -> anyhow::Result<()>,
+{
+    type Value = u64;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("a map from page paths to their contents")
+    }
+
+    fn visit_map<A>(mut self, mut map: A) -> Result<Self::Value, A::Error>
+    where
+        A: MapAccess<'de>,
+    {
+        let mut count = 0;
+        while let Some((path, content)) = map.next_entry::<String, String>()? {
+            (self.0)(path, content).map_err(A::Error::custom)?;
+            count += 1;
+        }
+        Ok(count)
+    }
+}
+
+/// Parse a `db.json` from `reader`, and write each page as soon as it's decoded.
+fn unpack_pages(
+    reader: impl Read,
+    mut writer: Box<dyn PageWriter>,
+    pb: &ProgressBar,
+) -> anyhow::Result<u64> {
+    let mut written = 0;
+    let write_page = |path: String, content: String| {
+        if !store::is_page_path(&path) {
+            anyhow::bail!("invalid page path: {}", path);
+        }
+        writer.write(&path, &content)?;
+        written += 1;
+        pb.set_message(format!("{} pages written", written));
+        Ok(())
+    };
+
+    let mut deserializer = serde_json::Deserializer::from_reader(BufReader::new(reader));
+    let count = deserializer.deserialize_map(PagesVisitor(write_page))?;
+    deserializer.end()?;
+    writer.finish()?;
+    Ok(count)
+}
@@ -0,0 +1,437 @@
+use std::{collections::HashMap, ops::Range, path::Path};
+
+use memmap2::Mmap;
+use serde::Serialize;
+
+use crate::{
+    html,
+    store::PageStore,
+    utils::io::{has_header, map_file, read_u32, read_u64},
+};
+
+use super::compact::source_stamp;
+
+/// The on-disk layout, all integers are little-endian:
+///
+/// ```text
+/// magic       b"DQFT"
+/// version     u32
+/// doc_count   u32
+/// term_count  u32
+/// total_len   u64     the number of terms in all pages
+/// source_len  u64     length of the `db.json` the pages were unpacked from
+/// source_time u64     mtime of that `db.json`, in nanoseconds
+/// docs        doc_count * [path_off, path_len, length]: u32
+/// terms       term_count * [term_off, term_len, postings_off, postings_len]: u32
+/// postings    * [doc, frequency]: u32
+/// strings     the utf-8 blob of paths and terms
+/// ```
+///
+/// Terms are sorted, and the postings of a term are sorted by doc.
+const MAGIC: &[u8; 4] = b"DQFT";
+const VERSION: u32 = 2;
+const HEADER_LEN: usize = 4 + 4 + 4 + 4 + 8 + 8 + 8;
+const DOC_LEN: usize = 3 * 4;
+const TERM_LEN: usize = 4 * 4;
+const POSTING_LEN: usize = 2 * 4;
+const MAX_TERM_LEN: usize = 64;
+
+/// BM25 parameters.
+const K1: f64 = 1.2;
+const B: f64 = 0.75;
+
+/// Split `text` into lowercase terms, with their byte ranges in `text`.
+pub fn tokenize(text: &str) -> impl Iterator<Item = (Range<usize>, String)> + '_ {
+    let mut chars = text.char_indices().peekable();
+    std::iter::from_fn(move || loop {
+        let (start, c) = chars.next()?;
+        if !c.is_alphanumeric() && c != '_' {
+            continue;
+        }
+        let mut end = start + c.len_utf8();
+        while let Some(&(i, c)) = chars.peek() {
+            if !c.is_alphanumeric() && c != '_' {
+                break;
+            }
+            end = i + c.len_utf8();
+            chars.next();
+        }
+        if end - start <= MAX_TERM_LEN {
+            return Some((start..end, text[start..end].to_lowercase()));
+        }
+    })
+}
+
+/// An inverted index of the text of every page of a docset.
+pub struct FullTextIndex {
+    data: Mmap,
+    doc_count: usize,
+    term_count: usize,
+    strings_off: usize,
+}
+
+/// A page matching a full-text query.
+#[derive(Debug, Clone)]
+pub struct FullTextHit {
+    pub path: String,
+    pub score: f64,
+}
+
+/// A piece of text around a match, with the byte ranges of the matched terms.
+#[derive(Debug, Clone, Serialize)]
+pub struct Snippet {
+    pub text: String,
+    pub highlights: Vec<(usize, usize)>,
+}
+
+impl FullTextIndex {
+    /// Index every page of `store`, which was unpacked from `source`, into `target`.
+    /// Returns the number of pages indexed.
+    pub fn build(store: &dyn PageStore, source: &Path, target: &Path) -> anyhow::Result<u64> {
+        // Without its `db.json`, the store can't change anymore.
+        let stamp = source_stamp(source).unwrap_or_default();
+        let mut paths = vec![];
+        let mut lengths = vec![];
+        let mut postings: HashMap<String, Vec<(u32, u32)>> = HashMap::new();
+        for path in store.paths() {
+            let Some(html) = store.read(&path)? else {
+                continue;
+            };
+            let doc = paths.len() as u32;
+            let mut frequencies: HashMap<String, u32> = HashMap::new();
+            let mut length = 0;
+            for (_, term) in tokenize(&html::plain_text(&html)) {
+                *frequencies.entry(term).or_default() += 1;
+                length += 1;
+            }
+            for (term, frequency) in frequencies {
+                postings.entry(term).or_default().push((doc, frequency));
+            }
+            paths.push(path);
+            lengths.push(length);
+        }
+
+        let mut terms = postings.into_iter().collect::<Vec<_>>();
+        terms.sort_by(|a, b| a.0.cmp(&b.0));
+
+        let mut strings = Vec::new();
+        let mut push = |s: &str, out: &mut Vec<u8>| {
+            out.extend_from_slice(&(strings.len() as u32).to_le_bytes());
+            out.extend_from_slice(&(s.len() as u32).to_le_bytes());
+            strings.extend_from_slice(s.as_bytes());
+        };
+        let mut docs = Vec::with_capacity(paths.len() * DOC_LEN);
+        for (path, length) in paths.iter().zip(lengths.iter()) {
+            push(path, &mut docs);
+            docs.extend_from_slice(&(*length as u32).to_le_bytes());
+        }
+        let mut records = Vec::with_capacity(terms.len() * TERM_LEN);
+        let mut posting_data = Vec::new();
+        for (term, list) in terms.iter() {
+            push(term, &mut records);
+            records.extend_from_slice(&((posting_data.len() / POSTING_LEN) as u32).to_le_bytes());
+            records.extend_from_slice(&(list.len() as u32).to_le_bytes());
+            for (doc, frequency) in list {
+                posting_data.extend_from_slice(&doc.to_le_bytes());
+                posting_data.extend_from_slice(&frequency.to_le_bytes());
+            }
+        }
+
+        let total_len = lengths.iter().map(|&len| len as u64).sum::<u64>();
+        let mut data = Vec::with_capacity(
+            HEADER_LEN + docs.len() + records.len() + posting_data.len() + strings.len(),
+        );
+        data.extend_from_slice(MAGIC);
+        data.extend_from_slice(&VERSION.to_le_bytes());
+        data.extend_from_slice(&(paths.len() as u32).to_le_bytes());
+        data.extend_from_slice(&(terms.len() as u32).to_le_bytes());
+        data.extend_from_slice(&total_len.to_le_bytes());
+        data.extend_from_slice(&stamp.0.to_le_bytes());
+        data.extend_from_slice(&stamp.1.to_le_bytes());
+        data.extend_from_slice(&docs);
+        data.extend_from_slice(&records);
+        data.extend_from_slice(&posting_data);
+        data.extend_from_slice(&strings);
+
+        let tmp = target.with_extension("bin.tmp");
+        std::fs::write(&tmp, data)?;
+        std::fs::rename(tmp, target)?;
+        Ok(paths.len() as u64)
+    }
+
+    /// Map the index at `path` and check every offset in it once, so the lookups don't
+    /// have to. `None` if it can't be read, an offset is out of the file, or its stamp is
+    /// not the one of `source`, which is taken as empty if it's missing.
+    pub fn open(path: &Path, source: &Path) -> Option<Self> {
+        let data = map_file(path).ok()?;
+        if !has_header(&data, MAGIC, VERSION, HEADER_LEN) {
+            return None;
+        }
+        let stamp = source_stamp(source).unwrap_or_default();
+        if (read_u64(&data, 24), read_u64(&data, 32)) != stamp {
+            return None;
+        }
+        let doc_count = read_u32(&data, 8) as usize;
+        let term_count = read_u32(&data, 12) as usize;
+        let records_end = HEADER_LEN
+            .checked_add(doc_count.checked_mul(DOC_LEN)?)?
+            .checked_add(term_count.checked_mul(TERM_LEN)?)?;
+        if data.len() < records_end {
+            return None;
+        }
+        let mut index = Self {
+            data,
+            doc_count,
+            term_count,
+            strings_off: 0,
+        };
+
+        // Validate every offset once, so lookups can index without checks.
+        let postings_count = (0..term_count)
+            .map(|i| index.term_record(i).3 as usize)
+            .try_fold(0usize, |sum, count| sum.checked_add(count))?;
+        let strings_off = records_end.checked_add(postings_count.checked_mul(POSTING_LEN)?)?;
+        let strings_len = index.data.len().checked_sub(strings_off)?;
+        let docs_ok = (0..doc_count).all(|i| {
+            let (off, len, _) = index.doc_record(i);
+            off as usize + len as usize <= strings_len
+        });
+        let terms_ok = (0..term_count).all(|i| {
+            let (off, len, postings_off, count) = index.term_record(i);
+            off as usize + len as usize <= strings_len
+                && postings_off as usize + count as usize <= postings_count
+        });
+        if !docs_ok || !terms_ok {
+            return None;
+        }
+        index.strings_off = strings_off;
+        Some(index)
+    }
+
+    fn doc_record(&self, i: usize) -> (u32, u32, u32) {
+        let offset = HEADER_LEN + i * DOC_LEN;
+        (
+            read_u32(&self.data, offset),
+            read_u32(&self.data, offset + 4),
+            read_u32(&self.data, offset + 8),
+        )
+    }
+
+    fn term_record(&self, i: usize) -> (u32, u32, u32, u32) {
+        let offset = HEADER_LEN + self.doc_count * DOC_LEN + i * TERM_LEN;
+        (
+            read_u32(&self.data, offset),
+            read_u32(&self.data, offset + 4),
+            read_u32(&self.data, offset + 8),
+            read_u32(&self.data, offset + 12),
+        )
+    }
+
+    fn string(&self, offset: u32, len: u32) -> &str {
+        let start = self.strings_off + offset as usize;
+        std::str::from_utf8(&self.data[start..start + len as usize]).unwrap_or_default()
+    }
+
+    /// The postings of `term`, as `(doc, frequency)`.
+    fn postings(&self, term: &str) -> Vec<(usize, u32)> {
+        let (mut low, mut high) = (0, self.term_count);
+        while low < high {
+            let mid = (low + high) / 2;
+            let (off, len, postings_off, count) = self.term_record(mid);
+            match self.string(off, len).cmp(term) {
+                std::cmp::Ordering::Less => low = mid + 1,
+                std::cmp::Ordering::Greater => high = mid,
+                std::cmp::Ordering::Equal => {
+                    let base = HEADER_LEN
+                        + self.doc_count * DOC_LEN
+                        + self.term_count * TERM_LEN
+                        + postings_off as usize * POSTING_LEN;
+                    return (0..count as usize)
+                        .map(|i| {
+                            let offset = base + i * POSTING_LEN;
+                            (
+                                read_u32(&self.data, offset) as usize,
+                                read_u32(&self.data, offset + 4),
+                            )
+                        })
+                        .collect();
+                }
+            }
+        }
+        vec![]
+    }
+
+    /// Rank the pages containing any term of `query` with BM25, best first.
+    pub fn search(&self, query: &str) -> Vec<FullTextHit> {
+        let mut terms = tokenize(query).map(|(_, term)| term).collect::<Vec<_>>();
+        terms.sort();
+        terms.dedup();
+
+        let n = self.doc_count as f64;
+        let average = read_u64(&self.data, 16) as f64 / n.max(1.0);
+        let mut scores: HashMap<usize, f64> = HashMap::new();
+        for term in terms.iter() {
+            let postings = self.postings(term);
+            let df = postings.len() as f64;
+            let idf = (1.0 + (n - df + 0.5) / (df + 0.5)).ln();
+            for (doc, frequency) in postings
+                .into_iter()
+                .filter(|(doc, _)| *doc < self.doc_count)
+            {
+                let length = self.doc_record(doc).2 as f64;
+                let tf = frequency as f64;
+                let score =
+                    idf * tf * (K1 + 1.0) / (tf + K1 * (1.0 - B + B * length / average.max(1.0)));
+                *scores.entry(doc).or_default() += score;
+            }
+        }
+
+        let mut hits = scores
+            .into_iter()
+            .map(|(doc, score)| {
+                let (off, len, _) = self.doc_record(doc);
+                FullTextHit {
+                    path: self.string(off, len).to_string(),
+                    score,
+                }
+            })
+            .collect::<Vec<_>>();
+        hits.sort_by(|a, b| b.score.total_cmp(&a.score).then(a.path.cmp(&b.path)));
+        hits
+    }
+}
+
+/// How many terms of `query` occur in `text`.
+pub fn count_matches(text: &str, query: &str) -> usize {
+    let terms = tokenize(query).map(|(_, term)| term).collect::<Vec<_>>();
+    tokenize(text)
+        .filter(|(_, term)| terms.contains(term))
+        .count()
+}
+
+/// Cut a snippet of about `width` chars out of `text`, around the first match of `query`.
+pub fn snippet(text: &str, query: &str, width: usize) -> Snippet {
+    let text = text.split_whitespace().collect::<Vec<_>>().join(" ");
+    let terms = tokenize(query).map(|(_, term)| term).collect::<Vec<_>>();
+    let matches = tokenize(&text)
+        .filter(|(_, term)| terms.contains(term))
+        .map(|(range, _)| range)
+        .collect::<Vec<_>>();
+
+    // Start a little before the first match, at a char boundary.
+    let first = matches.first().map(|range| range.start).unwrap_or(0);
+    let chars = text.char_indices().map(|(i, _)| i).collect::<Vec<_>>();
+    let first_char = chars.partition_point(|&i| i < first);
+    let mut start_char = first_char.saturating_sub(width / 4);
+    // Don't start in the middle of a word.
+    while start_char > 0
+        && start_char < first_char
+        && text[chars[start_char - 1]..].starts_with(|c: char| !c.is_whitespace())
+    {
+        start_char += 1;
+    }
+    let end_char = (start_char + width).min(chars.len());
+    let start = chars.get(start_char).copied().unwrap_or(text.len());
+    let end = chars.get(end_char).copied().unwrap_or(text.len());
+
+    let mut ret = String::new();
+    if start > 0 {
+        ret.push('…');
+    }
+    let shift = ret.len();
+    ret.push_str(&text[start..end]);
+    let highlights = matches
+        .into_iter()
+        .filter(|range| range.start >= start && range.end <= end)
+        .map(|range| (range.start - start + shift, range.end - start + shift))
+        .collect();
+    if end < text.len() {
+        ret.push('…');
+    }
+    Snippet {
+        text: ret,
+        highlights,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::store::{self, Storage};
+
+    /// Build the index of a few pages in `dir`, returning its path and its source.
+    fn build(dir: &Path) -> (std::path::PathBuf, std::path::PathBuf) {
+        let mut writer = store::create(dir, Storage::Files).unwrap();
+        writer
+            .write(
+                "array",
+                "<h1>Array</h1><p>An array of values, see push.</p>",
+            )
+            .unwrap();
+        writer
+            .write("push", "<h1>push</h1><p>Push a value: push, push.</p>")
+            .unwrap();
+        writer
+            .write("map", "<h1>Map</h1><p>Keys to values.</p>")
+            .unwrap();
+        writer.finish().unwrap();
+        let source = dir.join("db.json");
+        let target = dir.join("fulltext.bin");
+        std::fs::write(&source, "{}").unwrap();
+        let store = store::open(dir).unwrap();
+        assert_eq!(
+            FullTextIndex::build(store.as_ref(), &source, &target).unwrap(),
+            3
+        );
+        (target, source)
+    }
+
+    #[test]
+    fn ranks_with_bm25() {
+        let dir = tempfile::tempdir().unwrap();
+        let (target, source) = build(dir.path());
+        let index = FullTextIndex::open(&target, &source).unwrap();
+        let paths = |query| {
+            index
+                .search(query)
+                .into_iter()
+                .map(|hit| hit.path)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(paths("push"), ["push", "array"]);
+        assert_eq!(paths("VALUES keys"), ["map", "array"]);
+        assert!(paths("nothing").is_empty());
+    }
+
+    #[test]
+    fn tokenize_splits_words() {
+        let long = "x".repeat(MAX_TERM_LEN + 1);
+        let text = format!("Array.prototype.push(item_1) {} Émile", long);
+        let terms = tokenize(&text).collect::<Vec<_>>();
+        assert_eq!(
+            terms,
+            [
+                (0..5, "array".to_string()),
+                (6..15, "prototype".to_string()),
+                (16..20, "push".to_string()),
+                (21..27, "item_1".to_string()),
+                (95..101, "émile".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn is_stale_once_the_source_changes() {
+        let dir = tempfile::tempdir().unwrap();
+        let (target, source) = build(dir.path());
+        std::fs::write(&source, "{\"array\": \"\"}").unwrap();
+        assert!(FullTextIndex::open(&target, &source).is_none());
+    }
+
+    #[test]
+    fn snippet_highlights_matches() {
+        let snippet = snippet("Push a value onto the array.", "array push", 160);
+        assert_eq!(snippet.text, "Push a value onto the array.");
+        assert_eq!(snippet.highlights, [(0, 4), (22, 27)]);
+    }
+}
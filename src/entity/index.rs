@@ -19,3 +19,11 @@ pub struct Index {
     pub entries: Vec<IndexEntry>,
     pub types: Vec<IndexType>,
 }
+
+/// Split an entry path into the page path and the fragment, if any.
+pub fn split_fragment(path: &str) -> (&str, Option<&str>) {
+    match path.split_once('#') {
+        Some((page, fragment)) => (page, Some(fragment)),
+        None => (path, None),
+    }
+}
@@ -0,0 +1,130 @@
+use std::fmt;
+
+use reqwest::Url;
+use serde::Serialize;
+
+use super::split_fragment;
+
+/// Where a link of a page points to.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(untagged)]
+pub enum LinkTarget {
+    /// A page of a docset, the path may have a fragment.
+    Page { slug: String, path: String },
+    /// Anything else, as an absolute url.
+    External { url: String },
+}
+
+/// The pages of all docsets resolve links as if they were served from here, at
+/// `{slug}/{path}` like on devdocs.
+const PAGES_BASE: &str = "dq://pages/";
+
+fn decode(s: &str) -> String {
+    percent_encoding::percent_decode_str(s)
+        .decode_utf8_lossy()
+        .into_owned()
+}
+
+impl LinkTarget {
+    /// Resolve the `href` of a link found in the page at `path` of the docset `slug`.
+    pub fn resolve(slug: &str, path: &str, href: &str) -> Self {
+        let page = split_fragment(path).0;
+        let joined = Url::parse(PAGES_BASE)
+            .and_then(|base| base.join(&format!("{}/{}", slug, page)))
+            .and_then(|base| base.join(href));
+        match joined {
+            Ok(url) if url.scheme() == "dq" => {
+                // The url is percent-encoded, but the paths of the store are not.
+                let decoded = decode(url.path());
+                let rest = decoded.trim_start_matches('/');
+                // `/slug` is the index of the docset, like `/slug/`.
+                let (slug, page) = match rest.split_once('/') {
+                    Some(parts) => parts,
+                    None if !rest.is_empty() => (rest, ""),
+                    None => (slug, page),
+                };
+                let mut path = page.to_string();
+                if let Some(fragment) = url.fragment() {
+                    path.push('#');
+                    path.push_str(&decode(fragment));
+                }
+                Self::Page {
+                    slug: slug.to_string(),
+                    path,
+                }
+            }
+            Ok(url) => Self::External {
+                url: url.to_string(),
+            },
+            Err(_) => Self::External {
+                url: href.to_string(),
+            },
+        }
+    }
+}
+
+impl fmt::Display for LinkTarget {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Page { slug, path } => write!(f, "{} {}", slug, path),
+            Self::External { url } => f.write_str(url),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn page(slug: &str, path: &str) -> LinkTarget {
+        LinkTarget::Page {
+            slug: slug.to_string(),
+            path: path.to_string(),
+        }
+    }
+
+    #[test]
+    fn resolve() {
+        let cases = [
+            ("push", page("javascript", "global_objects/array/push")),
+            (
+                "../map#syntax",
+                page("javascript", "global_objects/map#syntax"),
+            ),
+            (
+                "#examples",
+                page("javascript", "global_objects/array/index#examples"),
+            ),
+            ("/css/color", page("css", "color")),
+            ("/css/", page("css", "")),
+            ("/css", page("css", "")),
+            ("/css#syntax", page("css", "#syntax")),
+            (
+                "https://tc39.es/ecma262/",
+                LinkTarget::External {
+                    url: "https://tc39.es/ecma262/".to_string(),
+                },
+            ),
+        ];
+        for (href, target) in cases {
+            assert_eq!(
+                LinkTarget::resolve("javascript", "global_objects/array/index", href),
+                target,
+                "{}",
+                href
+            );
+        }
+    }
+
+    #[test]
+    fn resolve_encoded() {
+        assert_eq!(
+            LinkTarget::resolve("cpp", "io/basic_ostream", "operator%3C%3C#std%3A%3Avector"),
+            page("cpp", "io/operator<<#std::vector")
+        );
+        assert_eq!(
+            LinkTarget::resolve("cpp", "io/operator<<", "#top"),
+            page("cpp", "io/operator<<#top")
+        );
+    }
+}
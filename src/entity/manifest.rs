@@ -0,0 +1,89 @@
+use std::collections::BTreeSet;
+
+use serde::{Deserialize, Serialize};
+
+use crate::context::Context;
+
+use super::{Docset, InstallStatus};
+
+const MANIFEST_FILE: &str = "installed.json";
+
+/// The docsets installed by `dq update SLUG...`, which a plain `dq update` refreshes.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct Manifest {
+    pub slugs: BTreeSet<String>,
+}
+
+impl Manifest {
+    /// Read the manifest from the cache. If there is none yet, it's made of the docsets of
+    /// `docsets` found in the cache, in any version.
+    pub async fn read(context: &Context, docsets: &[Docset]) -> anyhow::Result<Self> {
+        if context.cache_file_exists(MANIFEST_FILE) {
+            return context.read_from_cache(MANIFEST_FILE).await;
+        }
+        let slugs = docsets
+            .iter()
+            .filter(|docset| docset.install_status(context) != InstallStatus::NotInstalled)
+            .map(|docset| docset.slug.clone())
+            .collect();
+        Ok(Self { slugs })
+    }
+
+    pub fn add(&mut self, slugs: impl IntoIterator<Item = String>) {
+        self.slugs.extend(slugs);
+    }
+
+    /// Remove docsets, returning whether any of them was there.
+    pub fn remove(&mut self, slugs: &[String]) -> bool {
+        let len = self.slugs.len();
+        self.slugs.retain(|slug| !slugs.contains(slug));
+        self.slugs.len() != len
+    }
+
+    pub async fn write(&self, context: &Context) -> anyhow::Result<()> {
+        context.write_to_cache(MANIFEST_FILE, self).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::testing;
+
+    #[tokio::test]
+    async fn seeds_from_the_cache() {
+        let dir = tempfile::tempdir().unwrap();
+        let context = testing::context(dir.path()).await;
+        let docsets = ["a", "b"]
+            .into_iter()
+            .map(|slug| Docset {
+                name: slug.to_string(),
+                slug: slug.to_string(),
+                r#type: "simple".to_string(),
+                links: None,
+                version: None,
+                release: None,
+                mtime: 1700000000,
+                db_size: 0,
+            })
+            .collect::<Vec<_>>();
+        std::fs::create_dir_all(context.build_cache_path("a/1690000000")).unwrap();
+        std::fs::write(context.build_cache_path("a/1690000000/index.json"), "{}").unwrap();
+
+        let manifest = Manifest::read(&context, &docsets).await.unwrap();
+        assert_eq!(manifest.slugs, BTreeSet::from(["a".to_string()]));
+        // Once written, the cache is not looked at anymore.
+        Manifest::default().write(&context).await.unwrap();
+        let manifest = Manifest::read(&context, &docsets).await.unwrap();
+        assert!(manifest.slugs.is_empty());
+    }
+
+    #[test]
+    fn add_and_remove() {
+        let mut manifest = Manifest::default();
+        manifest.add(["a".to_string(), "b".to_string()]);
+        assert!(manifest.remove(&["a".to_string(), "c".to_string()]));
+        assert!(!manifest.remove(&["c".to_string()]));
+        assert_eq!(manifest.slugs, BTreeSet::from(["b".to_string()]));
+    }
+}
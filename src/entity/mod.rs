@@ -1,5 +1,13 @@
+mod compact;
 mod doc;
+mod fulltext;
 mod index;
+mod link;
+mod manifest;
 
+pub use compact::*;
 pub use doc::*;
+pub use fulltext::*;
 pub use index::*;
+pub use link::*;
+pub use manifest::*;
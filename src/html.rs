@@ -0,0 +1,387 @@
+use scraper::{ElementRef, Html};
+use serde::Serialize;
+
+/// The level of a heading element, `h1` is 1. `None` if it's not a heading.
+fn heading_level(element: &ElementRef) -> Option<u8> {
+    match element.value().name() {
+        "h1" => Some(1),
+        "h2" => Some(2),
+        "h3" => Some(3),
+        "h4" => Some(4),
+        "h5" => Some(5),
+        "h6" => Some(6),
+        _ => None,
+    }
+}
+
+pub fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+/// A piece of a section, an element or the text between two elements.
+enum Piece<'a> {
+    Element(ElementRef<'a>),
+    Text(&'a str),
+}
+
+/// Collect `start` and its following siblings until `stop` returns true.
+fn collect_until<'a>(start: ElementRef<'a>, stop: impl Fn(&ElementRef) -> bool) -> Vec<Piece<'a>> {
+    let mut pieces = vec![Piece::Element(start)];
+    for sibling in start.next_siblings() {
+        match ElementRef::wrap(sibling) {
+            Some(element) if stop(&element) => break,
+            Some(element) => pieces.push(Piece::Element(element)),
+            None => {
+                if let Some(text) = sibling.value().as_text() {
+                    pieces.push(Piece::Text(text));
+                }
+            }
+        }
+    }
+    pieces
+}
+
+/// The pieces of the section starting at `element`, and whether they make up a `dl`.
+///
+/// A heading runs until the next heading of the same or a higher level, and a `dt`
+/// takes its `dd`s along. Any other element is a section by itself.
+fn section_pieces(element: ElementRef<'_>) -> (Vec<Piece<'_>>, bool) {
+    if let Some(level) = heading_level(&element) {
+        let pieces = collect_until(element, |e| heading_level(e).is_some_and(|l| l <= level));
+        (pieces, false)
+    } else if element.value().name() == "dt" {
+        (collect_until(element, |e| e.value().name() != "dd"), true)
+    } else {
+        (vec![Piece::Element(element)], false)
+    }
+}
+
+/// The html of the section starting at `element`.
+fn section_from(element: ElementRef) -> String {
+    let (pieces, is_list) = section_pieces(element);
+    let mut html = String::new();
+    for piece in pieces {
+        match piece {
+            Piece::Element(element) => html.push_str(&element.html()),
+            Piece::Text(text) => html.push_str(&escape(text)),
+        }
+    }
+    if is_list {
+        format!("<dl>{}</dl>", html)
+    } else {
+        html
+    }
+}
+
+/// The text of the section starting at `element`, like [`plain_text`].
+fn section_text(element: ElementRef) -> String {
+    let mut text = String::new();
+    for piece in section_pieces(element).0 {
+        match piece {
+            Piece::Element(element) => {
+                text.push(' ');
+                push_text(element, &mut text);
+                text.push(' ');
+            }
+            Piece::Text(t) => text.push_str(t),
+        }
+    }
+    collapse_whitespace(&text)
+}
+
+fn collapse_whitespace(text: &str) -> String {
+    text.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+fn normalize_text(text: &str) -> String {
+    collapse_whitespace(text).to_lowercase()
+}
+
+/// Whether an element starts a new block of text, rather than being inline.
+pub fn is_block(name: &str) -> bool {
+    matches!(
+        name,
+        "address"
+            | "article"
+            | "aside"
+            | "blockquote"
+            | "dd"
+            | "details"
+            | "div"
+            | "dl"
+            | "dt"
+            | "figure"
+            | "footer"
+            | "h1"
+            | "h2"
+            | "h3"
+            | "h4"
+            | "h5"
+            | "h6"
+            | "header"
+            | "hr"
+            | "li"
+            | "main"
+            | "nav"
+            | "ol"
+            | "p"
+            | "pre"
+            | "section"
+            | "summary"
+            | "table"
+            | "ul"
+    )
+}
+
+/// Append the text of `element` to `text`, with blocks separated by spaces.
+fn push_text(element: ElementRef, text: &mut String) {
+    for child in element.children() {
+        if let Some(t) = child.value().as_text() {
+            text.push_str(t);
+        } else if let Some(child) = ElementRef::wrap(child) {
+            let block = is_block(child.value().name())
+                || matches!(child.value().name(), "br" | "td" | "th");
+            if block {
+                text.push(' ');
+            }
+            push_text(child, text);
+            if block {
+                text.push(' ');
+            }
+        }
+    }
+}
+
+/// A page parsed once, to take several sections out of it.
+pub struct Document(Html);
+
+impl Document {
+    pub fn parse(html: &str) -> Self {
+        Self(Html::parse_fragment(html))
+    }
+
+    fn element_by_id(&self, id: &str) -> Option<ElementRef<'_>> {
+        self.0
+            .tree
+            .nodes()
+            .filter_map(ElementRef::wrap)
+            .find(|element| element.value().id() == Some(id))
+    }
+
+    /// The section starting at the element with the given id.
+    pub fn section_by_id(&self, id: &str) -> Option<String> {
+        self.element_by_id(id).map(section_from)
+    }
+
+    /// The text of the section starting at the element with the given id.
+    pub fn section_text_by_id(&self, id: &str) -> Option<String> {
+        self.element_by_id(id).map(section_text)
+    }
+
+    /// The text of the page without any html, with its whitespace collapsed.
+    pub fn plain_text(&self) -> String {
+        let mut text = String::new();
+        push_text(self.0.root_element(), &mut text);
+        collapse_whitespace(&text)
+    }
+
+    /// The text of the first heading of the page.
+    pub fn title(&self) -> Option<String> {
+        let heading = self
+            .0
+            .tree
+            .nodes()
+            .filter_map(ElementRef::wrap)
+            .find(|element| heading_level(element).is_some())?;
+        Some(collapse_whitespace(&heading.text().collect::<String>()))
+    }
+}
+
+/// The text of a page without any html, with its whitespace collapsed.
+pub fn plain_text(html: &str) -> String {
+    Document::parse(html).plain_text()
+}
+
+/// Extract the section starting at the element with the given id.
+pub fn section_by_id(html: &str, id: &str) -> Option<String> {
+    Document::parse(html).section_by_id(id)
+}
+
+/// Extract the section under the heading with the given text, compared case-insensitively.
+pub fn section_by_heading(html: &str, heading: &str) -> Option<String> {
+    let document = Html::parse_fragment(html);
+    let heading = normalize_text(heading);
+    let element = document
+        .tree
+        .nodes()
+        .filter_map(ElementRef::wrap)
+        .filter(|element| heading_level(element).is_some())
+        .find(|element| normalize_text(&element.text().collect::<String>()) == heading)?;
+    Some(section_from(element))
+}
+
+/// The language of a code block, from `data-language` or a `language-*` class.
+pub fn code_language(element: ElementRef) -> Option<String> {
+    let code = element
+        .children()
+        .filter_map(ElementRef::wrap)
+        .find(|child| child.value().name() == "code");
+    [Some(element), code].into_iter().flatten().find_map(|e| {
+        e.value()
+            .attr("data-language")
+            .map(str::to_string)
+            .or_else(|| {
+                e.value().classes().find_map(|class| {
+                    class
+                        .strip_prefix("language-")
+                        .or_else(|| class.strip_prefix("lang-"))
+                        .map(str::to_string)
+                })
+            })
+    })
+}
+
+/// A link found in a page.
+#[derive(Debug, Clone, Serialize)]
+pub struct Link {
+    pub text: String,
+    pub href: String,
+}
+
+/// A preformatted code block found in a page.
+#[derive(Debug, Clone, Serialize)]
+pub struct CodeBlock {
+    pub language: Option<String>,
+    pub code: String,
+}
+
+/// The text of the first heading of a page.
+pub fn title(html: &str) -> Option<String> {
+    Document::parse(html).title()
+}
+
+/// Every link of a page, in order.
+pub fn links(html: &str) -> Vec<Link> {
+    let document = Html::parse_fragment(html);
+    document
+        .tree
+        .nodes()
+        .filter_map(ElementRef::wrap)
+        .filter(|element| element.value().name() == "a")
+        .filter_map(|element| {
+            let href = element.value().attr("href")?;
+            Some(Link {
+                text: collapse_whitespace(&element.text().collect::<String>()),
+                href: href.to_string(),
+            })
+        })
+        .collect()
+}
+
+/// Every `pre` block of a page, in order.
+pub fn code_blocks(html: &str) -> Vec<CodeBlock> {
+    let document = Html::parse_fragment(html);
+    document
+        .tree
+        .nodes()
+        .filter_map(ElementRef::wrap)
+        .filter(|element| element.value().name() == "pre")
+        .map(|element| CodeBlock {
+            language: code_language(element),
+            code: element.text().collect(),
+        })
+        .collect()
+}
+
+/// The length of the tag at the start of `html`, up to and including its `>`.
+fn tag_len(html: &str) -> usize {
+    if html.starts_with("<!--") {
+        return html.find("-->").map_or(html.len(), |end| end + 3);
+    }
+    let mut quote = None;
+    for (i, b) in html.bytes().enumerate().skip(1) {
+        match quote {
+            Some(q) if b == q => quote = None,
+            Some(_) => {}
+            None if b == b'"' || b == b'\'' => quote = Some(b),
+            None if b == b'>' => return i + 1,
+            None => {}
+        }
+    }
+    html.len()
+}
+
+/// The byte range of the value of the `href` attribute of a tag, with its quotes.
+fn href_range(tag: &str) -> Option<(usize, usize)> {
+    let bytes = tag.as_bytes();
+    let mut quote = None;
+    for i in 1..bytes.len() {
+        match quote {
+            Some(q) if bytes[i] == q => quote = None,
+            Some(_) => {}
+            None if bytes[i] == b'"' || bytes[i] == b'\'' => quote = Some(bytes[i]),
+            None if bytes[i - 1].is_ascii_whitespace()
+                && bytes[i..].len() >= 4
+                && bytes[i..i + 4].eq_ignore_ascii_case(b"href") =>
+            {
+                let rest = &tag[i + 4..];
+                let value = rest.trim_start().strip_prefix('=')?.trim_start();
+                let start = tag.len() - value.len();
+                return match value.as_bytes().first()? {
+                    q @ (b'"' | b'\'') => {
+                        let len = value[1..].find(*q as char)?;
+                        Some((start, start + len + 2))
+                    }
+                    _ => {
+                        let len = value
+                            .find(|c: char| c.is_ascii_whitespace() || c == '>')
+                            .unwrap_or(value.len());
+                        Some((start, start + len))
+                    }
+                };
+            }
+            None => {}
+        }
+    }
+    None
+}
+
+/// Replace the `href` of every link of a page with what `rewrite` returns for it. The
+/// rest of the html is kept as is.
+pub fn rewrite_links(html: &str, rewrite: impl Fn(&str) -> String) -> String {
+    let mut ret = String::with_capacity(html.len());
+    let mut rest = html;
+    while let Some(start) = rest.find('<') {
+        ret.push_str(&rest[..start]);
+        rest = &rest[start..];
+        let tag = &rest[..tag_len(rest)];
+        rest = &rest[tag.len()..];
+
+        let is_link = tag.len() > 2
+            && tag.as_bytes()[1].eq_ignore_ascii_case(&b'a')
+            && tag.as_bytes()[2].is_ascii_whitespace();
+        let range = is_link.then(|| href_range(tag)).flatten();
+        // Let the parser decode the entities of the href.
+        let href = range.and_then(|_| {
+            Html::parse_fragment(tag)
+                .tree
+                .nodes()
+                .filter_map(ElementRef::wrap)
+                .find_map(|element| element.value().attr("href").map(str::to_string))
+        });
+        match (range, href) {
+            (Some((start, end)), Some(href)) => {
+                ret.push_str(&tag[..start]);
+                ret.push('"');
+                ret.push_str(&escape(&rewrite(&href)).replace('"', "&quot;"));
+                ret.push('"');
+                ret.push_str(&tag[end..]);
+            }
+            _ => ret.push_str(tag),
+        }
+    }
+    ret.push_str(rest);
+    ret
+}
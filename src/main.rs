@@ -3,12 +3,17 @@ mod command;
 mod config;
 mod context;
 mod entity;
+mod html;
+mod render;
+mod server;
+mod store;
 mod utils;
 
 use std::path::PathBuf;
 
-use clap::{Parser, Subcommand};
+use clap::{builder::FalseyValueParser, Parser, Subcommand};
 use command::Command as _;
+use config::Config;
 use context::Context;
 
 #[derive(Debug, Parser)]
@@ -26,6 +31,43 @@ struct Cli {
     /// The number of concurrent downloads.
     #[arg(global = true, short, long, default_value = "5")]
     limit: Option<usize>,
+    /// The url of the docsets metadata. Accepts `http(s)://` and `file://` urls.
+    #[arg(global = true, long, env = "DQ_META_URL")]
+    meta_url: Option<String>,
+    /// The base url of the documents. Accepts `http(s)://` and `file://` urls.
+    #[arg(global = true, long, env = "DQ_DOCUMENTS_URL")]
+    documents_url: Option<String>,
+    /// Never touch the network, only use what's in the cache.
+    #[arg(global = true, long, default_value = "false")]
+    offline: bool,
+    /// How to store the pages of newly downloaded docsets.
+    #[arg(global = true, long, value_enum)]
+    storage: Option<store::Storage>,
+    /// The proxy for all requests, e.g. `http://proxy.example.com:8080`.
+    #[arg(global = true, long, env = "DQ_PROXY")]
+    proxy: Option<String>,
+    /// Hosts that bypass `--proxy`, separated by commas.
+    #[arg(global = true, long, env = "DQ_NO_PROXY", value_delimiter = ',')]
+    no_proxy: Option<Vec<String>>,
+    /// Extra root CA certificates to trust, in PEM or DER. Can be repeated. Defaults to
+    /// `$DQ_CA_CERT`, a list of paths separated like `$PATH`.
+    #[arg(global = true, long)]
+    ca_cert: Option<Vec<PathBuf>>,
+    /// The timeout to connect to a server, in seconds.
+    #[arg(global = true, long, env = "DQ_CONNECT_TIMEOUT")]
+    connect_timeout: Option<u64>,
+    /// The timeout of each read from a server, in seconds.
+    #[arg(global = true, long, env = "DQ_READ_TIMEOUT")]
+    read_timeout: Option<u64>,
+    /// How many times to retry a failed download.
+    #[arg(global = true, long, env = "DQ_RETRIES")]
+    retries: Option<u32>,
+    /// Ignore the proxies set by the system, like `HTTPS_PROXY`.
+    #[arg(global = true, long, env = "DQ_NO_SYSTEM_PROXY", value_parser = FalseyValueParser::new())]
+    no_system_proxy: bool,
+    /// The User-Agent header sent with every request.
+    #[arg(global = true, long, env = "DQ_USER_AGENT")]
+    user_agent: Option<String>,
 }
 
 #[derive(Debug, Subcommand)]
@@ -36,34 +78,108 @@ enum Commands {
     Search(command::search::SearchArgs),
     /// Display a doc page.
     Cat(command::cat::CatArgs),
+    /// List the available docsets.
+    List(command::list::ListArgs),
+    /// Remove installed docsets.
+    Remove(command::remove::RemoveArgs),
+    /// Remove the outdated versions of installed docsets.
+    Gc(command::gc::GcArgs),
+    /// Pick an entry interactively, with a live preview of its page.
+    Pick(command::pick::PickArgs),
+    /// Check the installed docsets for missing or broken pages.
+    Verify(command::verify::VerifyArgs),
+    /// Move the pages of installed docsets into the storage set by `--storage`.
+    Migrate(command::migrate::MigrateArgs),
+    /// List the types of entries in a docset, with their counts.
+    Types(command::types::TypesArgs),
+    /// Run a long-running server for editors, keeping the indexes loaded.
+    Serve(command::serve::ServeArgs),
+    /// Read or edit the config file.
+    Config(command::config::ConfigArgs),
+    /// Print the completion script of a shell.
+    Completions(command::completions::CompletionsArgs),
+    /// Print the completions of an argument, for the completion scripts.
+    #[command(hide = true)]
+    Complete(command::completions::CompleteArgs),
 }
 
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
-    let mut context = Context::new().await;
-
     let cli = Cli::parse();
-    cli.update_config(&mut context);
+    let mut config = Config::new_from_file();
+    cli.update_config(&mut config);
+
+    let mut context = Context::new(config).await?;
 
     match cli.command {
         Commands::Update(args) => args.run(&mut context).await?,
         Commands::Search(args) => args.run(&mut context).await?,
         Commands::Cat(args) => args.run(&mut context).await?,
+        Commands::List(args) => args.run(&mut context).await?,
+        Commands::Remove(args) => args.run(&mut context).await?,
+        Commands::Gc(args) => args.run(&mut context).await?,
+        Commands::Pick(args) => args.run(&mut context).await?,
+        Commands::Verify(args) => args.run(&mut context).await?,
+        Commands::Migrate(args) => args.run(&mut context).await?,
+        Commands::Types(args) => args.run(&mut context).await?,
+        Commands::Serve(args) => args.run(&mut context).await?,
+        Commands::Config(args) => args.run(&mut context).await?,
+        Commands::Completions(args) => args.run(&mut context).await?,
+        Commands::Complete(args) => args.run(&mut context).await?,
     }
 
     Ok(())
 }
 
 impl Cli {
-    fn update_config(&self, context: &mut Context) {
+    fn update_config(&self, config: &mut Config) {
         if let Some(cache_dir) = &self.cache_dir {
-            context.config.cache_dir = Some(cache_dir.clone());
+            config.cache_dir = Some(cache_dir.clone());
         }
         if self.no_progress {
-            context.config.progress = Some(false);
+            config.progress = Some(false);
         }
         if let Some(limit) = self.limit {
-            context.config.limit = Some(limit);
+            config.limit = Some(limit);
+        }
+        if let Some(meta_url) = &self.meta_url {
+            config.meta_url = Some(meta_url.clone());
+        }
+        if let Some(documents_url) = &self.documents_url {
+            config.documents_url = Some(documents_url.clone());
+        }
+        if self.offline {
+            config.offline = Some(true);
+        }
+        if let Some(storage) = self.storage {
+            config.storage = Some(storage);
+        }
+        if let Some(proxy) = &self.proxy {
+            config.proxy = Some(proxy.clone());
+        }
+        if let Some(no_proxy) = &self.no_proxy {
+            config.no_proxy = Some(no_proxy.clone());
+        }
+        if let Some(ca_cert) = &self.ca_cert {
+            config.ca_certs = Some(ca_cert.clone());
+        } else if let Some(paths) = std::env::var_os("DQ_CA_CERT") {
+            let paths = std::env::split_paths(&paths).filter(|path| !path.as_os_str().is_empty());
+            config.ca_certs = Some(paths.collect());
+        }
+        if let Some(connect_timeout) = self.connect_timeout {
+            config.connect_timeout = Some(connect_timeout);
+        }
+        if let Some(read_timeout) = self.read_timeout {
+            config.read_timeout = Some(read_timeout);
+        }
+        if let Some(retries) = self.retries {
+            config.retries = Some(retries);
+        }
+        if self.no_system_proxy {
+            config.system_proxy = Some(false);
+        }
+        if let Some(user_agent) = &self.user_agent {
+            config.user_agent = Some(user_agent.clone());
         }
     }
 }
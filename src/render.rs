@@ -0,0 +1,340 @@
+use std::io::Cursor;
+
+use html2text::{
+    self,
+    render::text_renderer::{RichAnnotation, TrivialDecorator},
+};
+use scraper::{ElementRef, Html};
+
+use crate::html;
+
+fn default_colour_map(annotations: &[RichAnnotation], s: &str) -> String {
+    use termion::color::*;
+    use RichAnnotation::*;
+    let mut have_explicit_colour = true;
+    let mut start = Vec::new();
+    let mut finish = Vec::new();
+    for annotation in annotations.iter() {
+        match annotation {
+            Default => {}
+            Link(_) => {
+                start.push(format!("{}", termion::style::Underline));
+                finish.push(format!("{}", termion::style::Reset));
+            }
+            Image(_) if !have_explicit_colour => {
+                start.push(format!("{}", Fg(Blue)));
+                finish.push(format!("{}", Fg(Reset)));
+            }
+            Emphasis => {
+                start.push(format!("{}", termion::style::Bold));
+                finish.push(format!("{}", termion::style::Reset));
+            }
+            Strong if !have_explicit_colour => {
+                start.push(format!("{}", Fg(LightYellow)));
+                finish.push(format!("{}", Fg(Reset)));
+            }
+            Strikeout if !have_explicit_colour => {
+                start.push(format!("{}", Fg(LightBlack)));
+                finish.push(format!("{}", Fg(Reset)));
+            }
+            Code if !have_explicit_colour => {
+                start.push(format!("{}", Fg(Blue)));
+                finish.push(format!("{}", Fg(Reset)));
+            }
+            Preformat(_) if !have_explicit_colour => {
+                start.push(format!("{}", Fg(Blue)));
+                finish.push(format!("{}", Fg(Reset)));
+            }
+            Colour(c) => {
+                start.push(format!("{}", Fg(Rgb(c.r, c.g, c.b))));
+                finish.push(format!("{}", Fg(Reset)));
+                have_explicit_colour = true;
+            }
+            BgColour(c) => {
+                start.push(format!("{}", Bg(Rgb(c.r, c.g, c.b))));
+                finish.push(format!("{}", Bg(Reset)));
+            }
+            _ => {}
+        }
+    }
+    // Reverse the finish sequences
+    finish.reverse();
+    let mut result = start.join("");
+    result.push_str(s);
+    for s in finish {
+        result.push_str(&s);
+    }
+    result
+}
+
+/// Render a html page into ANSI-coloured text.
+pub fn render_ansi(html: &str, width: usize) -> anyhow::Result<String> {
+    let config = html2text::config::rich()
+        .use_doc_css()
+        .max_wrap_width(width);
+    let ret = config.coloured(Cursor::new(html), width, move |anns, s| {
+        default_colour_map(anns, s)
+    })?;
+    Ok(ret)
+}
+
+/// Render a html page into plain text, without any markup.
+pub fn render_text(html: &str, width: usize) -> anyhow::Result<String> {
+    let ret = html2text::config::with_decorator(TrivialDecorator::new())
+        .max_wrap_width(width)
+        .string_from_read(Cursor::new(html), width)?;
+    Ok(ret)
+}
+
+/// Collapse the whitespace of a text node, and escape what markdown would interpret.
+fn markdown_text(text: &str) -> String {
+    let mut ret = String::with_capacity(text.len());
+    let mut space = false;
+    for c in text.chars() {
+        if c.is_whitespace() {
+            space = true;
+            continue;
+        }
+        if space {
+            ret.push(' ');
+            space = false;
+        }
+        if matches!(c, '\\' | '*' | '_' | '`' | '[' | ']') {
+            ret.push('\\');
+        }
+        ret.push(c);
+    }
+    if space {
+        ret.push(' ');
+    }
+    ret
+}
+
+/// Wrap `code` in enough backticks that none inside it end the span.
+fn markdown_code_span(code: &str) -> String {
+    let fence = "`".repeat(code.split(|c| c != '`').map(str::len).max().unwrap_or(0) + 1);
+    let pad = if code.starts_with('`') || code.ends_with('`') {
+        " "
+    } else {
+        ""
+    };
+    format!("{fence}{pad}{code}{pad}{fence}")
+}
+
+/// Render the children of `element` as inline markdown.
+fn markdown_inline(element: ElementRef) -> String {
+    let mut ret = String::new();
+    for child in element.children() {
+        if let Some(text) = child.value().as_text() {
+            ret.push_str(&markdown_text(text));
+        } else if let Some(element) = ElementRef::wrap(child) {
+            ret.push_str(&markdown_element(element));
+        }
+    }
+    ret
+}
+
+/// Render an inline element as markdown.
+fn markdown_element(element: ElementRef) -> String {
+    match element.value().name() {
+        "code" | "kbd" | "samp" | "tt" => {
+            let code = element.text().collect::<String>();
+            markdown_code_span(code.trim())
+        }
+        "strong" | "b" => format!("**{}**", markdown_inline(element).trim()),
+        "em" | "i" | "var" => format!("*{}*", markdown_inline(element).trim()),
+        "del" | "s" => format!("~~{}~~", markdown_inline(element).trim()),
+        "br" => "  \n".to_string(),
+        "img" => {
+            let alt = element.value().attr("alt").unwrap_or_default();
+            let src = element.value().attr("src").unwrap_or_default();
+            format!("![{}]({})", markdown_text(alt), src)
+        }
+        "a" => {
+            let text = markdown_inline(element);
+            match element.value().attr("href") {
+                Some(href) if !text.trim().is_empty() => format!("[{}]({})", text.trim(), href),
+                _ => text,
+            }
+        }
+        _ => markdown_inline(element),
+    }
+}
+
+/// Prefix the first line of `block` with `first`, and the others with `rest`.
+fn indent(block: &str, first: &str, rest: &str) -> String {
+    block
+        .lines()
+        .enumerate()
+        .map(|(i, line)| {
+            let prefix = if i == 0 { first } else { rest };
+            if line.is_empty() {
+                prefix.trim_end().to_string()
+            } else {
+                format!("{}{}", prefix, line)
+            }
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Render a markdown table, the first row is the header.
+fn markdown_table(table: ElementRef) -> String {
+    let rows = table
+        .descendants()
+        .filter_map(ElementRef::wrap)
+        .filter(|e| e.value().name() == "tr")
+        .map(|row| {
+            row.children()
+                .filter_map(ElementRef::wrap)
+                .filter(|cell| matches!(cell.value().name(), "td" | "th"))
+                .map(|cell| markdown_inline(cell).trim().replace('|', "\\|"))
+                .collect::<Vec<_>>()
+        })
+        .filter(|row| !row.is_empty())
+        .collect::<Vec<_>>();
+    let columns = rows.iter().map(Vec::len).max().unwrap_or(0);
+    let mut lines = vec![];
+    for (i, row) in rows.iter().enumerate() {
+        let cells = (0..columns)
+            .map(|c| row.get(c).map(String::as_str).unwrap_or_default())
+            .collect::<Vec<_>>();
+        lines.push(format!("| {} |", cells.join(" | ")));
+        if i == 0 {
+            lines.push(format!("|{}", " --- |".repeat(columns)));
+        }
+    }
+    lines.join("\n")
+}
+
+/// Render the children of `element` as markdown blocks, separated by blank lines.
+fn markdown_blocks(element: ElementRef) -> String {
+    let mut blocks = vec![];
+    let mut paragraph = String::new();
+    let flush = |paragraph: &mut String, blocks: &mut Vec<String>| {
+        let text = paragraph.trim();
+        if !text.is_empty() {
+            blocks.push(text.to_string());
+        }
+        paragraph.clear();
+    };
+
+    for child in element.children() {
+        let Some(element) = ElementRef::wrap(child).filter(|e| html::is_block(e.value().name()))
+        else {
+            if let Some(text) = child.value().as_text() {
+                paragraph.push_str(&markdown_text(text));
+            } else if let Some(element) = ElementRef::wrap(child) {
+                paragraph.push_str(&markdown_element(element));
+            }
+            continue;
+        };
+        flush(&mut paragraph, &mut blocks);
+
+        let name = element.value().name();
+        let block = match name {
+            "h1" | "h2" | "h3" | "h4" | "h5" | "h6" => {
+                let level = name[1..].parse::<usize>().unwrap_or(1);
+                format!("{} {}", "#".repeat(level), markdown_inline(element).trim())
+            }
+            "p" | "dt" | "summary" => markdown_inline(element).trim().to_string(),
+            "pre" => {
+                let code = element.text().collect::<String>();
+                let fence = if code.contains("```") { "~~~" } else { "```" };
+                format!(
+                    "{}{}\n{}\n{}",
+                    fence,
+                    html::code_language(element).unwrap_or_default(),
+                    code.trim_end_matches('\n'),
+                    fence
+                )
+            }
+            "hr" => "---".to_string(),
+            "blockquote" => indent(&markdown_blocks(element), "> ", "> "),
+            "table" => markdown_table(element),
+            "ul" | "ol" => {
+                let ordered = name == "ol";
+                element
+                    .children()
+                    .filter_map(ElementRef::wrap)
+                    .filter(|item| item.value().name() == "li")
+                    .enumerate()
+                    .map(|(i, item)| {
+                        let marker = if ordered {
+                            format!("{}. ", i + 1)
+                        } else {
+                            "- ".to_string()
+                        };
+                        let rest = " ".repeat(marker.len());
+                        indent(&markdown_blocks(item), &marker, &rest)
+                    })
+                    .collect::<Vec<_>>()
+                    .join("\n")
+            }
+            _ => markdown_blocks(element),
+        };
+        if !block.trim().is_empty() {
+            blocks.push(block);
+        }
+    }
+    flush(&mut paragraph, &mut blocks);
+    blocks.join("\n\n")
+}
+
+/// Render a html page into markdown.
+pub fn render_markdown(html: &str) -> String {
+    let document = Html::parse_fragment(html);
+    let mut ret = markdown_blocks(document.root_element());
+    ret.push('\n');
+    ret
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn markdown() {
+        let cases = [
+            ("<h1>Array</h1>", "# Array\n"),
+            (
+                "<h3>The <code>push()</code> method</h3>",
+                "### The `push()` method\n",
+            ),
+            (
+                "<p>Calls <em>f</em> on <strong>each</strong> item_</p><p>Then stops.</p>",
+                "Calls *f* on **each** item\\_\n\nThen stops.\n",
+            ),
+            (
+                "<pre data-language=\"js\">a.push(1);\n</pre>",
+                "```js\na.push(1);\n```\n",
+            ),
+            (
+                "<pre class=\"language-md\">```\ncode\n```</pre>",
+                "~~~md\n```\ncode\n```\n~~~\n",
+            ),
+            ("<p>Use <code>a`b</code></p>", "Use ``a`b``\n"),
+            (
+                "<ul><li>one</li><li><p>two</p><p>more</p></li></ul>",
+                "- one\n- two\n\n  more\n",
+            ),
+            ("<ol><li>one</li><li>two</li></ol>", "1. one\n2. two\n"),
+            (
+                "<ul><li>outer<ul><li>inner</li></ul></li></ul>",
+                "- outer\n\n  - inner\n",
+            ),
+            (
+                "<p>See <a href=\"array/push\">push</a> and <a href=\"#x\"></a>.</p>",
+                "See [push](array/push) and .\n",
+            ),
+            (
+                "<table><tr><th>Name</th><th>Type</th></tr><tr><td>a|b</td></tr></table>",
+                "| Name | Type |\n| --- | --- |\n| a\\|b |  |\n",
+            ),
+            ("<blockquote><p>Note</p></blockquote>", "> Note\n"),
+        ];
+        for (html, expected) in cases {
+            assert_eq!(render_markdown(html), expected, "{}", html);
+        }
+    }
+}
@@ -0,0 +1,361 @@
+use std::{collections::BTreeMap, convert::Infallible, net::SocketAddr, sync::Arc};
+
+use bytes::Bytes;
+use http_body_util::Full;
+use hyper::{
+    body::Incoming, header, server::conn::http1, service::service_fn, Method, Request, Response,
+    StatusCode, Uri,
+};
+use hyper_util::rt::TokioIo;
+use percent_encoding::{utf8_percent_encode, AsciiSet, CONTROLS};
+use tokio::net::TcpListener;
+
+use crate::{
+    entity::{split_fragment, Docset, InstallStatus, LinkTarget},
+    html::{self, escape},
+};
+
+use super::{SearchParams, Server};
+
+const STYLE: &str = "body { max-width: 60rem; margin: 0 auto; padding: 1rem; \
+    font-family: sans-serif; line-height: 1.5 } \
+    pre { background: #f4f4f4; padding: 0.5rem; overflow-x: auto } \
+    nav { border-bottom: 1px solid #ddd; margin-bottom: 1rem }";
+
+type Body = Full<Bytes>;
+
+/// An error answered with its status and message.
+struct HttpError(StatusCode, String);
+
+impl HttpError {
+    fn not_found(message: impl ToString) -> Self {
+        Self(StatusCode::NOT_FOUND, message.to_string())
+    }
+}
+
+fn html_response(title: &str, nav: &str, body: &str) -> Response<Body> {
+    let page = format!(
+        "<!DOCTYPE html>\n<html><head><meta charset=\"utf-8\"><title>{}</title>\
+         <style>{}</style></head><body><nav>{}</nav>{}</body></html>\n",
+        escape(title),
+        STYLE,
+        nav,
+        body
+    );
+    Response::builder()
+        .header(header::CONTENT_TYPE, "text/html; charset=utf-8")
+        .body(Full::from(page))
+        .unwrap()
+}
+
+fn json_response(status: StatusCode, value: &serde_json::Value) -> Response<Body> {
+    Response::builder()
+        .status(status)
+        .header(header::CONTENT_TYPE, "application/json")
+        .body(Full::from(value.to_string()))
+        .unwrap()
+}
+
+/// Redirect to `location`, which must be encoded already: a 400 if it's not a valid header.
+fn redirect(location: &str) -> Result<Response<Body>, HttpError> {
+    Response::builder()
+        .status(StatusCode::FOUND)
+        .header(header::LOCATION, location)
+        .body(Full::default())
+        .map_err(|_| HttpError(StatusCode::BAD_REQUEST, "invalid path".to_string()))
+}
+
+/// What is percent-encoded in the path of a route, and in its fragment.
+const PATH: &AsciiSet = &CONTROLS
+    .add(b' ')
+    .add(b'"')
+    .add(b'#')
+    .add(b'%')
+    .add(b'<')
+    .add(b'>')
+    .add(b'?')
+    .add(b'`')
+    .add(b'{')
+    .add(b'}');
+const FRAGMENT: &AsciiSet = &CONTROLS.add(b' ').add(b'"').add(b'<').add(b'>').add(b'`');
+
+/// The local route of a page of a docset, the path may have a fragment.
+fn page_route(slug: &str, path: &str) -> String {
+    let (page, fragment) = split_fragment(path);
+    let mut route = format!(
+        "/{}/{}",
+        utf8_percent_encode(slug, PATH),
+        utf8_percent_encode(page, PATH)
+    );
+    if let Some(fragment) = fragment {
+        route.push('#');
+        route.extend(utf8_percent_encode(fragment, FRAGMENT));
+    }
+    route
+}
+
+fn nav(docset: Option<&Docset>) -> String {
+    let mut nav = String::from("<a href=\"/\">Docsets</a>");
+    if let Some(docset) = docset {
+        nav.push_str(&format!(
+            " / <a href=\"/{}/\">{}</a>",
+            escape(&docset.slug),
+            escape(&docset_title(docset))
+        ));
+    }
+    nav
+}
+
+fn docset_title(docset: &Docset) -> String {
+    match &docset.version {
+        Some(version) if !version.is_empty() => format!("{} {}", docset.name, version),
+        _ => docset.name.clone(),
+    }
+}
+
+/// Answer an error as json for the search endpoint, or as an html page.
+fn error_response(json: bool, HttpError(status, message): HttpError) -> Response<Body> {
+    if json {
+        return json_response(status, &serde_json::json!({ "error": message }));
+    }
+    let mut response = html_response(
+        status.as_str(),
+        &nav(None),
+        &format!("<h1>{}</h1><p>{}</p>", status, escape(&message)),
+    );
+    *response.status_mut() = status;
+    response
+}
+
+/// Build the params of a search from a query string, like
+/// `q=push&slugs=javascript,node&type=Array&full_text=true&max_results=20`. Without
+/// `slugs`, every installed docset is searched.
+fn search_params(query: &str) -> Result<SearchParams, HttpError> {
+    let bad_request = |message: String| HttpError(StatusCode::BAD_REQUEST, message);
+    let mut params = SearchParams {
+        slugs: vec![],
+        query: String::new(),
+        installed: false,
+        types: vec![],
+        full_text: false,
+        max_results: None,
+        matcher: Default::default(),
+    };
+    for (key, value) in form_urlencoded::parse(query.as_bytes()) {
+        match key.as_ref() {
+            "q" => params.query = value.into_owned(),
+            "slugs" | "slug" => params.slugs.extend(
+                value
+                    .split(',')
+                    .filter(|slug| !slug.is_empty())
+                    .map(str::to_string),
+            ),
+            "type" => params.types.push(value.into_owned()),
+            "full_text" => params.full_text = value != "false" && value != "0",
+            "max_results" => {
+                params.max_results = Some(
+                    value
+                        .parse()
+                        .map_err(|_| bad_request(format!("invalid max_results {}", value)))?,
+                )
+            }
+            "matcher" => {
+                params.matcher = serde_json::from_value(serde_json::json!(value))
+                    .map_err(|_| bad_request(format!("invalid matcher {}", value)))?
+            }
+            _ => {}
+        }
+    }
+    if params.query.is_empty() {
+        return Err(bad_request("missing q".to_string()));
+    }
+    params.installed = params.slugs.is_empty();
+    Ok(params)
+}
+
+impl Server {
+    /// Serve the installed docsets as html, and a search endpoint at `/search` returning
+    /// the same results as `search --format json`.
+    pub async fn serve_http(self: Arc<Self>, addr: SocketAddr) -> anyhow::Result<()> {
+        let listener = TcpListener::bind(addr)
+            .await
+            .map_err(|e| anyhow::anyhow!("Bind {}, err: {}", addr, e))?;
+        eprintln!("Serving on http://{}", listener.local_addr()?);
+
+        loop {
+            let stream = match listener.accept().await {
+                Ok((stream, _)) => stream,
+                Err(e) => {
+                    eprintln!("warning: accept a connection, err: {}", e);
+                    continue;
+                }
+            };
+            let server = self.clone();
+            tokio::spawn(async move {
+                let service = service_fn(move |request| server.clone().respond(request));
+                let _ = http1::Builder::new()
+                    .serve_connection(TokioIo::new(stream), service)
+                    .await;
+            });
+        }
+    }
+
+    async fn respond(
+        self: Arc<Self>,
+        request: Request<Incoming>,
+    ) -> Result<Response<Body>, Infallible> {
+        if request.method() != Method::GET && request.method() != Method::HEAD {
+            return Ok(Response::builder()
+                .status(StatusCode::METHOD_NOT_ALLOWED)
+                .body(Full::default())
+                .unwrap());
+        }
+        // Reading pages, parsing them and building indexes all block, so they are kept
+        // off the threads serving the connections.
+        let uri = request.uri().clone();
+        let runtime = tokio::runtime::Handle::current();
+        let response = tokio::task::spawn_blocking(move || runtime.block_on(self.route(&uri)))
+            .await
+            .unwrap_or_else(|e| {
+                error_response(
+                    false,
+                    HttpError(StatusCode::INTERNAL_SERVER_ERROR, e.to_string()),
+                )
+            });
+        Ok(response)
+    }
+
+    async fn route(&self, uri: &Uri) -> Response<Body> {
+        let path = uri.path();
+        let is_search = path == "/search";
+        let response = if is_search {
+            self.search_route(uri.query().unwrap_or_default()).await
+        } else {
+            self.page_route(path).await
+        };
+        response.unwrap_or_else(|e| error_response(is_search, e))
+    }
+
+    async fn search_route(&self, query: &str) -> Result<Response<Body>, HttpError> {
+        let params = search_params(query)?;
+        let results = self
+            .search(params)
+            .await
+            .map_err(|e| HttpError(StatusCode::BAD_REQUEST, e.to_string()))?;
+        Ok(json_response(StatusCode::OK, &results))
+    }
+
+    async fn page_route(&self, path: &str) -> Result<Response<Body>, HttpError> {
+        let path = percent_encoding::percent_decode_str(path)
+            .decode_utf8()
+            .map_err(|_| HttpError(StatusCode::BAD_REQUEST, "invalid path".to_string()))?;
+        let Some((slug, page)) = path.trim_start_matches('/').split_once('/') else {
+            return match path.as_ref() {
+                "/" => Ok(self.docsets_page()),
+                path => redirect(&page_route(path.trim_start_matches('/'), "")),
+            };
+        };
+
+        let docset = Docset::resolve(&self.context, &self.docsets, slug)
+            .map_err(HttpError::not_found)?
+            .docset;
+        if docset.slug != slug {
+            return redirect(&page_route(&docset.slug, page));
+        }
+        if page.is_empty() {
+            return self.entries_page(docset).await;
+        }
+
+        let content = docset
+            .read_page(&self.context, split_fragment(page).0)
+            .await
+            .map_err(HttpError::not_found)?;
+        let content = html::rewrite_links(&content, |href| {
+            match LinkTarget::resolve(&docset.slug, page, href) {
+                LinkTarget::Page { slug, path } => page_route(&slug, &path),
+                LinkTarget::External { url } => url,
+            }
+        });
+        let title = html::title(&content).unwrap_or_else(|| page.to_string());
+        Ok(html_response(&title, &nav(Some(docset)), &content))
+    }
+
+    /// List the installed docsets.
+    fn docsets_page(&self) -> Response<Body> {
+        let mut body = String::from("<h1>Docsets</h1><ul>");
+        for docset in self
+            .docsets
+            .iter()
+            .filter(|docset| docset.install_status(&self.context) == InstallStatus::Installed)
+        {
+            body.push_str(&format!(
+                "<li><a href=\"/{}/\">{}</a></li>",
+                escape(&docset.slug),
+                escape(&docset_title(docset))
+            ));
+        }
+        body.push_str("</ul>");
+        html_response("Docsets", &nav(None), &body)
+    }
+
+    /// List the entries of a docset, grouped by type.
+    async fn entries_page(&self, docset: &Docset) -> Result<Response<Body>, HttpError> {
+        let index = self.index(docset).await.map_err(HttpError::not_found)?;
+        let mut types = BTreeMap::<&str, Vec<_>>::new();
+        for entry in index.iter() {
+            types
+                .entry(entry.r#type.unwrap_or("Other"))
+                .or_default()
+                .push(entry);
+        }
+
+        let title = docset_title(docset);
+        let mut body = format!("<h1>{}</h1>", escape(&title));
+        for (name, entries) in types {
+            body.push_str(&format!("<h2>{}</h2><ul>", escape(name)));
+            for entry in entries {
+                body.push_str(&format!(
+                    "<li><a href=\"{}\">{}</a></li>",
+                    escape(&page_route(&docset.slug, entry.path)).replace('"', "&quot;"),
+                    escape(entry.name)
+                ));
+            }
+            body.push_str("</ul>");
+        }
+        Ok(html_response(&title, &nav(Some(docset)), &body))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{config::Config, context::Context};
+
+    #[tokio::test]
+    async fn redirects_a_slug_encoded() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = Config {
+            cache_dir: Some(dir.path().to_path_buf()),
+            ..Default::default()
+        };
+        let server = Server::new(Context::new(config).await.unwrap(), vec![]);
+        for (path, location) in [
+            ("/%C3%A9", "/%C3%A9/"),
+            ("/%0A", "/%0A/"),
+            ("/a%20b", "/a%20b/"),
+        ] {
+            let response = server.route(&path.parse().unwrap()).await;
+            assert_eq!(response.status(), StatusCode::FOUND, "{}", path);
+            assert_eq!(response.headers()[header::LOCATION], location);
+        }
+    }
+
+    #[test]
+    fn rejects_an_invalid_location() {
+        assert!(redirect("/%0A/").is_ok());
+        let Err(HttpError(status, _)) = redirect("/\n/") else {
+            panic!("a newline is not a valid location");
+        };
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+    }
+}
@@ -0,0 +1,178 @@
+use std::{collections::HashMap, sync::Arc};
+
+use parking_lot::Mutex;
+use serde::Deserialize;
+
+use crate::{
+    command::{
+        cat::{render_page, PageFormat},
+        search::{search_entries, search_full_text, select_docsets, Matcher},
+    },
+    context::Context,
+    entity::{CompactIndex, Docset, InstallStatus},
+    html,
+};
+
+pub mod http;
+pub mod stdio;
+
+/// The state shared by the requests of a long-running server. `docsets.json` is read
+/// once, and the index of each docset is kept loaded after its first search.
+pub struct Server {
+    context: Context,
+    docsets: Vec<Docset>,
+    indexes: Mutex<HashMap<String, Arc<CompactIndex>>>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct DocsetsParams {
+    /// Only list docsets that have been downloaded, in any version.
+    #[serde(default)]
+    pub installed: bool,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct SearchParams {
+    /// The docsets to search, resolved like the slugs given to `search`.
+    #[serde(default)]
+    pub slugs: Vec<String>,
+    pub query: String,
+    /// Search in every installed docset.
+    #[serde(default)]
+    pub installed: bool,
+    /// Only search entries of these types.
+    #[serde(default)]
+    pub types: Vec<String>,
+    /// Search the text of the pages instead of the names of the entries.
+    #[serde(default)]
+    pub full_text: bool,
+    pub max_results: Option<usize>,
+    #[serde(default)]
+    pub matcher: Matcher,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct RenderParams {
+    pub slug: String,
+    /// The page to render. If it has a `#fragment`, only that section is rendered.
+    pub path: String,
+    /// Only render the section under the heading with this text.
+    pub section: Option<String>,
+    #[serde(default = "default_format")]
+    pub format: PageFormat,
+    #[serde(default = "default_width")]
+    pub width: usize,
+}
+
+fn default_format() -> PageFormat {
+    PageFormat::Text
+}
+
+fn default_width() -> usize {
+    160
+}
+
+impl Server {
+    pub fn new(context: Context, docsets: Vec<Docset>) -> Self {
+        Self {
+            context,
+            docsets,
+            indexes: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Read the compact index of a docset, or reuse it if it's been read before.
+    async fn index(&self, docset: &Docset) -> anyhow::Result<Arc<CompactIndex>> {
+        if let Some(index) = self.indexes.lock().get(&docset.slug) {
+            return Ok(index.clone());
+        }
+        let index = Arc::new(docset.read_compact_index(&self.context).await?);
+        self.indexes
+            .lock()
+            .insert(docset.slug.clone(), index.clone());
+        Ok(index)
+    }
+
+    /// List the docsets, with the same fields as `list --format json`.
+    pub fn docsets(&self, params: DocsetsParams) -> serde_json::Value {
+        let docsets = self
+            .docsets
+            .iter()
+            .map(|docset| (docset, docset.install_status(&self.context)))
+            .filter(|(_, status)| !params.installed || *status != InstallStatus::NotInstalled)
+            .map(|(docset, status)| {
+                serde_json::json!({
+                    "docset": docset,
+                    "status": status,
+                })
+            })
+            .collect::<Vec<_>>();
+        serde_json::Value::Array(docsets)
+    }
+
+    /// Search one or more docsets, with the same results as `search --format json`.
+    pub async fn search(&self, params: SearchParams) -> anyhow::Result<serde_json::Value> {
+        let matcher = params.matcher.to_matcher();
+        let named = params
+            .slugs
+            .iter()
+            .map(|slug| Ok(Docset::resolve(&self.context, &self.docsets, slug)?.docset))
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        let selected = select_docsets(&self.context, &self.docsets, named, params.installed)?;
+        let indexes =
+            futures::future::try_join_all(selected.iter().map(|docset| self.index(docset))).await?;
+        let indexes = indexes.iter().map(Arc::as_ref).collect::<Vec<_>>();
+
+        let results = if params.full_text {
+            search_full_text(
+                &self.context,
+                matcher.as_ref(),
+                &selected,
+                &indexes,
+                &params.query,
+                &params.types,
+                params.max_results.unwrap_or(20),
+            )
+            .await?
+        } else {
+            search_entries(
+                matcher.as_ref(),
+                &selected,
+                &indexes,
+                &params.query,
+                &params.types,
+                params.max_results,
+            )
+        };
+        Ok(results.iter().map(|result| result.to_json()).collect())
+    }
+
+    /// Render a page like `cat`. With the `json` format, the page is returned as an
+    /// object, otherwise as `{slug, path, title, content}`.
+    pub async fn render(&self, params: RenderParams) -> anyhow::Result<serde_json::Value> {
+        let doc = Docset::resolve(&self.context, &self.docsets, &params.slug)?.docset;
+        let mut content = doc.read_entry(&self.context, &params.path).await?;
+        if let Some(section) = &params.section {
+            content = html::section_by_heading(&content, section).ok_or_else(|| {
+                anyhow::anyhow!("section {} not found in {}", section, params.path)
+            })?;
+        }
+        let title = html::title(&content).unwrap_or_else(|| params.path.clone());
+        let rendered = render_page(
+            &doc.slug,
+            &params.path,
+            content,
+            params.format,
+            params.width,
+        )?;
+        if let PageFormat::Json = params.format {
+            return Ok(serde_json::from_str(&rendered)?);
+        }
+        Ok(serde_json::json!({
+            "slug": doc.slug,
+            "path": params.path,
+            "title": title,
+            "content": rendered,
+        }))
+    }
+}
@@ -0,0 +1,177 @@
+use serde::{de::DeserializeOwned, Serialize};
+use serde_json::Value;
+use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWrite, AsyncWriteExt, BufReader};
+
+use super::Server;
+
+const PARSE_ERROR: i64 = -32700;
+const INVALID_REQUEST: i64 = -32600;
+const METHOD_NOT_FOUND: i64 = -32601;
+const INVALID_PARAMS: i64 = -32602;
+/// Errors of the methods themselves, e.g. a docset that is not installed.
+const SERVER_ERROR: i64 = -32000;
+
+#[derive(Serialize, Debug)]
+struct RpcError {
+    code: i64,
+    message: String,
+}
+
+impl RpcError {
+    fn new(code: i64, message: impl ToString) -> Self {
+        Self {
+            code,
+            message: message.to_string(),
+        }
+    }
+}
+
+fn response(id: Value, result: Result<Value, RpcError>) -> Value {
+    match result {
+        Ok(result) => serde_json::json!({ "jsonrpc": "2.0", "id": id, "result": result }),
+        Err(error) => serde_json::json!({ "jsonrpc": "2.0", "id": id, "error": error }),
+    }
+}
+
+fn parse_params<T: DeserializeOwned>(params: Value) -> Result<T, RpcError> {
+    // A method without required params may be called without any.
+    let params = if params.is_null() {
+        Value::Object(Default::default())
+    } else {
+        params
+    };
+    serde_json::from_value(params).map_err(|e| RpcError::new(INVALID_PARAMS, e))
+}
+
+impl Server {
+    async fn call(&self, method: &str, params: Value) -> Result<Value, RpcError> {
+        let server_error = |e: anyhow::Error| RpcError::new(SERVER_ERROR, e);
+        match method {
+            "docsets" => Ok(self.docsets(parse_params(params)?)),
+            "search" => self
+                .search(parse_params(params)?)
+                .await
+                .map_err(server_error),
+            "render" => self
+                .render(parse_params(params)?)
+                .await
+                .map_err(server_error),
+            _ => Err(RpcError::new(
+                METHOD_NOT_FOUND,
+                format!("method {} not found", method),
+            )),
+        }
+    }
+
+    /// Handle a single request, returning nothing for notifications.
+    async fn handle(&self, request: Value) -> Option<Value> {
+        let Value::Object(mut request) = request else {
+            return Some(response(
+                Value::Null,
+                Err(RpcError::new(INVALID_REQUEST, "request must be an object")),
+            ));
+        };
+        let id = request.remove("id");
+        let result = match request.remove("method") {
+            Some(Value::String(method)) => {
+                let params = request.remove("params").unwrap_or(Value::Null);
+                self.call(&method, params).await
+            }
+            _ => Err(RpcError::new(INVALID_REQUEST, "method must be a string")),
+        };
+        id.map(|id| response(id, result))
+    }
+
+    /// Handle a message, which is a request or a batch of them.
+    async fn handle_message(&self, message: &[u8]) -> Option<Value> {
+        let message = match serde_json::from_slice::<Value>(message) {
+            Ok(message) => message,
+            Err(e) => return Some(response(Value::Null, Err(RpcError::new(PARSE_ERROR, e)))),
+        };
+        match message {
+            Value::Array(requests) => {
+                let mut responses = vec![];
+                for request in requests {
+                    responses.extend(self.handle(request).await);
+                }
+                (!responses.is_empty()).then_some(Value::Array(responses))
+            }
+            request => self.handle(request).await,
+        }
+    }
+
+    /// Serve JSON-RPC 2.0 requests from stdin until it's closed.
+    ///
+    /// Messages are read either one per line, or framed with a `Content-Length` header like
+    /// the language server protocol. Each response is framed the same way as its request.
+    pub async fn serve_stdio(&self) -> anyhow::Result<()> {
+        let mut stdin = BufReader::new(tokio::io::stdin());
+        let mut stdout = tokio::io::stdout();
+        let mut line = String::new();
+        loop {
+            line.clear();
+            if stdin.read_line(&mut line).await? == 0 {
+                return Ok(());
+            }
+            let header = line.trim();
+            if header.is_empty() {
+                continue;
+            }
+            let Some(length) = content_length(header) else {
+                if let Some(response) = self.handle_message(header.as_bytes()).await {
+                    write_message(&mut stdout, &response, false).await?;
+                }
+                continue;
+            };
+            let length = length?;
+            // Skip the other headers, up to the empty line.
+            loop {
+                line.clear();
+                if stdin.read_line(&mut line).await? == 0 {
+                    return Ok(());
+                }
+                if line.trim().is_empty() {
+                    break;
+                }
+            }
+            let mut message = vec![0; length];
+            stdin.read_exact(&mut message).await?;
+            if let Some(response) = self.handle_message(&message).await {
+                write_message(&mut stdout, &response, true).await?;
+            }
+        }
+    }
+}
+
+/// Parse a `Content-Length` header, or return `None` if it's not one.
+fn content_length(header: &str) -> Option<anyhow::Result<usize>> {
+    let (name, value) = header.split_once(':')?;
+    if !name.trim().eq_ignore_ascii_case("content-length") {
+        return None;
+    }
+    Some(
+        value
+            .trim()
+            .parse()
+            .map_err(|e| anyhow::anyhow!("Parse header {}, err: {}", header, e)),
+    )
+}
+
+async fn write_message(
+    writer: &mut (impl AsyncWrite + Unpin),
+    message: &Value,
+    framed: bool,
+) -> anyhow::Result<()> {
+    let message = serde_json::to_string(message)?;
+    if framed {
+        writer
+            .write_all(format!("Content-Length: {}\r\n\r\n", message.len()).as_bytes())
+            .await?;
+        writer.write_all(message.as_bytes()).await?;
+    } else {
+        writer.write_all(message.as_bytes()).await?;
+        writer.write_all(b"\n").await?;
+    }
+    writer.flush().await?;
+    Ok(())
+}
@@ -0,0 +1,462 @@
+use std::{
+    fmt,
+    fs::File,
+    io::{BufWriter, Read, Write},
+    path::{Component, Path, PathBuf},
+};
+
+use clap::ValueEnum;
+use flate2::{read::DeflateDecoder, write::DeflateEncoder, Compression};
+use memmap2::Mmap;
+use serde::{Deserialize, Serialize};
+
+use crate::utils::{
+    fs::walk_files,
+    io::{has_header, map_file, read_u32, read_u64},
+};
+
+/// How the pages of a docset are stored.
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Storage {
+    /// One `db/{path}/_index` file per page.
+    Files,
+    /// All pages compressed into a single `db.pack` file.
+    Archive,
+}
+
+impl fmt::Display for Storage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Storage::Files => f.write_str("files"),
+            Storage::Archive => f.write_str("archive"),
+        }
+    }
+}
+
+/// Read access to the pages of a docset.
+pub trait PageStore: Send + Sync {
+    /// Read a page, `None` if it's not in the store.
+    fn read(&self, path: &str) -> anyhow::Result<Option<String>>;
+    /// List everything in the store. Anything that is not a page is listed with its own
+    /// path, so it's never mistaken for one.
+    fn paths(&self) -> Vec<String>;
+}
+
+/// Write access to the pages of a docset.
+pub trait PageWriter: Send {
+    fn write(&mut self, path: &str, content: &str) -> anyhow::Result<()>;
+    /// Finish writing, replacing the pages in any other storage.
+    fn finish(self: Box<Self>) -> anyhow::Result<()>;
+}
+
+/// Whether `path` is a relative path that stays inside the store, so it can be joined
+/// to the directory of the pages.
+pub fn is_page_path(path: &str) -> bool {
+    Path::new(path)
+        .components()
+        .all(|c| matches!(c, Component::Normal(_)))
+}
+
+const ARCHIVE_FILE: &str = "db.pack";
+const FILES_DIRECTORY: &str = "db";
+
+/// Open the pages of the docset in `base_directory`, in whichever storage they are.
+pub fn open(base_directory: &Path) -> anyhow::Result<Box<dyn PageStore>> {
+    let archive = base_directory.join(ARCHIVE_FILE);
+    if archive.exists() {
+        Ok(Box::new(ArchiveStore::open(&archive)?))
+    } else {
+        Ok(Box::new(FilesStore {
+            root: base_directory.join(FILES_DIRECTORY),
+        }))
+    }
+}
+
+/// Create a writer for the pages of the docset in `base_directory`.
+pub fn create(base_directory: &Path, storage: Storage) -> anyhow::Result<Box<dyn PageWriter>> {
+    match storage {
+        Storage::Files => Ok(Box::new(FilesWriter {
+            base_directory: base_directory.to_path_buf(),
+        })),
+        Storage::Archive => Ok(Box::new(ArchiveWriter::create(base_directory)?)),
+    }
+}
+
+/// The storage the docset in `base_directory` is currently using.
+pub fn current_storage(base_directory: &Path) -> Storage {
+    if base_directory.join(ARCHIVE_FILE).exists() {
+        Storage::Archive
+    } else {
+        Storage::Files
+    }
+}
+
+/// Move the pages of the docset in `base_directory` into `storage`, returning how many
+/// pages were moved, or `None` if they are already there.
+pub fn migrate(base_directory: &Path, storage: Storage) -> anyhow::Result<Option<u64>> {
+    if current_storage(base_directory) == storage {
+        return Ok(None);
+    }
+    let store = open(base_directory)?;
+    let mut writer = create(base_directory, storage)?;
+    let mut count = 0;
+    for path in store.paths() {
+        // Stray files in `db/` are not pages, and are left behind.
+        if let Some(content) = store.read(&path)? {
+            writer.write(&path, &content)?;
+            count += 1;
+        }
+    }
+    writer.finish()?;
+    Ok(Some(count))
+}
+
+struct FilesStore {
+    root: PathBuf,
+}
+
+impl PageStore for FilesStore {
+    fn read(&self, path: &str) -> anyhow::Result<Option<String>> {
+        if !is_page_path(path) {
+            return Ok(None);
+        }
+        let filename = self.root.join(path).join("_index");
+        match std::fs::read_to_string(filename) {
+            Ok(content) => Ok(Some(content)),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    fn paths(&self) -> Vec<String> {
+        walk_files(&self.root)
+            .into_iter()
+            .map(|file| {
+                match (
+                    file.file_name().and_then(|name| name.to_str()),
+                    file.parent(),
+                ) {
+                    (Some("_index"), Some(parent)) => parent.to_string_lossy().to_string(),
+                    _ => file.to_string_lossy().to_string(),
+                }
+            })
+            .collect()
+    }
+}
+
+struct FilesWriter {
+    base_directory: PathBuf,
+}
+
+impl PageWriter for FilesWriter {
+    fn write(&mut self, path: &str, content: &str) -> anyhow::Result<()> {
+        let filename = self
+            .base_directory
+            .join(FILES_DIRECTORY)
+            .join(path)
+            .join("_index");
+        if let Some(parent) = filename.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        std::fs::write(&filename, content.as_bytes())?;
+        Ok(())
+    }
+
+    fn finish(self: Box<Self>) -> anyhow::Result<()> {
+        match std::fs::remove_file(self.base_directory.join(ARCHIVE_FILE)) {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e.into()),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// The archive layout, all integers are little-endian:
+///
+/// ```text
+/// magic   b"DQPK"
+/// version u32
+/// pages   the deflate-compressed pages
+/// records count * [path_off: u32, path_len: u32, data_off: u64, data_len: u32, raw_len: u32]
+/// strings the utf-8 blob of paths the records point into
+/// footer  [records_off: u64, count: u64, magic: b"DQPK"]
+/// ```
+///
+/// Records are sorted by path, so a page can be found with a binary search.
+const MAGIC: &[u8; 4] = b"DQPK";
+const VERSION: u32 = 1;
+const HEADER_LEN: u64 = 8;
+const RECORD_LEN: usize = 24;
+const FOOTER_LEN: usize = 20;
+/// Deflate never compresses data to less than about a thousandth of its size.
+const MAX_RATIO: usize = 1032;
+
+struct ArchiveWriter {
+    file: BufWriter<File>,
+    tmp: PathBuf,
+    base_directory: PathBuf,
+    offset: u64,
+    /// `(path, data_off, data_len, raw_len)` of every page written.
+    records: Vec<(String, u64, u32, u32)>,
+}
+
+impl ArchiveWriter {
+    fn create(base_directory: &Path) -> anyhow::Result<Self> {
+        std::fs::create_dir_all(base_directory)?;
+        let tmp = base_directory.join(format!("{}.tmp", ARCHIVE_FILE));
+        let mut file = BufWriter::new(File::create(&tmp)?);
+        file.write_all(MAGIC)?;
+        file.write_all(&VERSION.to_le_bytes())?;
+        Ok(Self {
+            file,
+            tmp,
+            base_directory: base_directory.to_path_buf(),
+            offset: HEADER_LEN,
+            records: vec![],
+        })
+    }
+}
+
+impl PageWriter for ArchiveWriter {
+    fn write(&mut self, path: &str, content: &str) -> anyhow::Result<()> {
+        let mut encoder = DeflateEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(content.as_bytes())?;
+        let data = encoder.finish()?;
+        self.file.write_all(&data)?;
+        self.records.push((
+            path.to_string(),
+            self.offset,
+            data.len() as u32,
+            content.len() as u32,
+        ));
+        self.offset += data.len() as u64;
+        Ok(())
+    }
+
+    fn finish(mut self: Box<Self>) -> anyhow::Result<()> {
+        // A page written twice keeps its last content: the sort is stable, so after the
+        // reverse the last write of a path comes first and survives the dedup.
+        self.records.reverse();
+        self.records.sort_by(|a, b| a.0.cmp(&b.0));
+        self.records.dedup_by(|a, b| a.0 == b.0);
+
+        let mut strings = Vec::new();
+        for (path, data_off, data_len, raw_len) in self.records.iter() {
+            self.file.write_all(&(strings.len() as u32).to_le_bytes())?;
+            self.file.write_all(&(path.len() as u32).to_le_bytes())?;
+            self.file.write_all(&data_off.to_le_bytes())?;
+            self.file.write_all(&data_len.to_le_bytes())?;
+            self.file.write_all(&raw_len.to_le_bytes())?;
+            strings.extend_from_slice(path.as_bytes());
+        }
+        self.file.write_all(&strings)?;
+        self.file.write_all(&self.offset.to_le_bytes())?;
+        self.file
+            .write_all(&(self.records.len() as u64).to_le_bytes())?;
+        self.file.write_all(MAGIC)?;
+        self.file.flush()?;
+
+        std::fs::rename(&self.tmp, self.base_directory.join(ARCHIVE_FILE))?;
+        let files = self.base_directory.join(FILES_DIRECTORY);
+        if files.exists() {
+            std::fs::remove_dir_all(files)?;
+        }
+        Ok(())
+    }
+}
+
+struct ArchiveStore {
+    data: Mmap,
+    count: usize,
+    records_off: usize,
+    strings_off: usize,
+}
+
+struct Record {
+    path: (usize, usize),
+    data: (usize, usize),
+    raw_len: usize,
+}
+
+impl ArchiveStore {
+    /// Map the archive at `path`, checking its header, its footer and that the records
+    /// fit in it. The pages are checked when they are read.
+    fn open(path: &Path) -> anyhow::Result<Self> {
+        let data = map_file(path)?;
+        let corrupted = || anyhow::anyhow!("{} is corrupted", path.display());
+
+        let len = data.len();
+        if !has_header(&data, MAGIC, VERSION, HEADER_LEN as usize + FOOTER_LEN)
+            || &data[len - 4..] != MAGIC
+        {
+            return Err(corrupted());
+        }
+        let records_off = read_u64(&data, len - FOOTER_LEN) as usize;
+        let count = read_u64(&data, len - FOOTER_LEN + 8) as usize;
+        let strings_off = count
+            .checked_mul(RECORD_LEN)
+            .and_then(|size| size.checked_add(records_off))
+            .filter(|&off| off <= len - FOOTER_LEN)
+            .ok_or_else(corrupted)?;
+
+        Ok(Self {
+            data,
+            count,
+            records_off,
+            strings_off,
+        })
+    }
+
+    fn record(&self, i: usize) -> Record {
+        let offset = self.records_off + i * RECORD_LEN;
+        Record {
+            path: (
+                read_u32(&self.data, offset) as usize,
+                read_u32(&self.data, offset + 4) as usize,
+            ),
+            data: (
+                read_u64(&self.data, offset + 8) as usize,
+                read_u32(&self.data, offset + 16) as usize,
+            ),
+            raw_len: read_u32(&self.data, offset + 20) as usize,
+        }
+    }
+
+    fn slice(&self, start: usize, len: usize, end: usize) -> Option<&[u8]> {
+        let stop = start.checked_add(len)?;
+        (stop <= end).then(|| &self.data[start..stop])
+    }
+
+    fn path(&self, record: &Record) -> &[u8] {
+        let end = self.data.len() - FOOTER_LEN;
+        self.slice(self.strings_off + record.path.0, record.path.1, end)
+            .unwrap_or_default()
+    }
+
+    fn find(&self, path: &str) -> Option<Record> {
+        let (mut low, mut high) = (0, self.count);
+        while low < high {
+            let mid = (low + high) / 2;
+            let record = self.record(mid);
+            match self.path(&record).cmp(path.as_bytes()) {
+                std::cmp::Ordering::Equal => return Some(record),
+                std::cmp::Ordering::Less => low = mid + 1,
+                std::cmp::Ordering::Greater => high = mid,
+            }
+        }
+        None
+    }
+}
+
+impl PageStore for ArchiveStore {
+    fn read(&self, path: &str) -> anyhow::Result<Option<String>> {
+        let Some(record) = self.find(path) else {
+            return Ok(None);
+        };
+        let data = self
+            .slice(record.data.0, record.data.1, self.records_off)
+            .ok_or_else(|| anyhow::anyhow!("page {} is out of the archive", path))?;
+        // `raw_len` is only trusted as far as deflate could have compressed the page.
+        let mut content = String::with_capacity(record.raw_len.min(data.len() * MAX_RATIO));
+        DeflateDecoder::new(data).read_to_string(&mut content)?;
+        if content.len() != record.raw_len {
+            anyhow::bail!("page {} is corrupted", path);
+        }
+        Ok(Some(content))
+    }
+
+    fn paths(&self) -> Vec<String> {
+        (0..self.count)
+            .map(|i| String::from_utf8_lossy(self.path(&self.record(i))).to_string())
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PAGES: [(&str, &str); 3] = [
+        ("array/push", "<h1>push</h1>"),
+        ("array", "<h1>Array</h1>"),
+        ("map", "<h1>Map</h1>"),
+    ];
+
+    fn write(base_directory: &Path, storage: Storage, pages: &[(&str, &str)]) {
+        let mut writer = create(base_directory, storage).unwrap();
+        for (path, content) in pages {
+            writer.write(path, content).unwrap();
+        }
+        writer.finish().unwrap();
+    }
+
+    fn read_all(base_directory: &Path) -> Vec<(String, String)> {
+        let store = open(base_directory).unwrap();
+        let mut paths = store.paths();
+        paths.sort();
+        paths
+            .into_iter()
+            .map(|path| {
+                let content = store.read(&path).unwrap().unwrap();
+                (path, content)
+            })
+            .collect()
+    }
+
+    fn expected() -> Vec<(String, String)> {
+        let mut pages = PAGES
+            .iter()
+            .map(|(path, content)| (path.to_string(), content.to_string()))
+            .collect::<Vec<_>>();
+        pages.sort();
+        pages
+    }
+
+    #[test]
+    fn round_trip() {
+        for storage in [Storage::Files, Storage::Archive] {
+            let dir = tempfile::tempdir().unwrap();
+            write(dir.path(), storage, &PAGES);
+            assert_eq!(current_storage(dir.path()), storage);
+            assert_eq!(read_all(dir.path()), expected());
+            assert_eq!(open(dir.path()).unwrap().read("missing").unwrap(), None);
+        }
+    }
+
+    #[test]
+    fn migrate_both_ways() {
+        let dir = tempfile::tempdir().unwrap();
+        write(dir.path(), Storage::Files, &PAGES);
+        assert_eq!(migrate(dir.path(), Storage::Archive).unwrap(), Some(3));
+        assert!(!dir.path().join(FILES_DIRECTORY).exists());
+        assert_eq!(read_all(dir.path()), expected());
+        assert_eq!(migrate(dir.path(), Storage::Archive).unwrap(), None);
+        assert_eq!(migrate(dir.path(), Storage::Files).unwrap(), Some(3));
+        assert!(!dir.path().join(ARCHIVE_FILE).exists());
+        assert_eq!(read_all(dir.path()), expected());
+    }
+
+    #[test]
+    fn archive_keeps_the_last_write() {
+        let dir = tempfile::tempdir().unwrap();
+        write(
+            dir.path(),
+            Storage::Archive,
+            &[("map", "first"), ("array", "array"), ("map", "last")],
+        );
+        let store = open(dir.path()).unwrap();
+        assert_eq!(store.paths(), ["array", "map"]);
+        assert_eq!(store.read("map").unwrap().as_deref(), Some("last"));
+    }
+
+    #[test]
+    fn files_reject_paths_out_of_the_store() {
+        let dir = tempfile::tempdir().unwrap();
+        write(dir.path(), Storage::Files, &PAGES);
+        std::fs::write(dir.path().join("_index"), "secret").unwrap();
+        let store = open(dir.path()).unwrap();
+        for path in ["..", "../db/map", "/etc"] {
+            assert_eq!(store.read(path).unwrap(), None, "{}", path);
+        }
+    }
+}
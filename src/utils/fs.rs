@@ -0,0 +1,35 @@
+use std::path::{Path, PathBuf};
+
+/// The total size of all files under `path`, in bytes.
+pub fn dir_size(path: &Path) -> u64 {
+    let Ok(dir) = std::fs::read_dir(path) else {
+        return 0;
+    };
+    dir.filter_map(|entry| entry.ok())
+        .map(|entry| match entry.file_type() {
+            Ok(t) if t.is_dir() => dir_size(&entry.path()),
+            Ok(_) => entry.metadata().map(|m| m.len()).unwrap_or(0),
+            Err(_) => 0,
+        })
+        .sum()
+}
+
+/// List all files under `root`, relative to it.
+pub fn walk_files(root: &Path) -> Vec<PathBuf> {
+    fn walk(root: &Path, relative: &Path, files: &mut Vec<PathBuf>) {
+        let Ok(dir) = std::fs::read_dir(root.join(relative)) else {
+            return;
+        };
+        for entry in dir.filter_map(|entry| entry.ok()) {
+            let path = relative.join(entry.file_name());
+            match entry.file_type() {
+                Ok(t) if t.is_dir() => walk(root, &path, files),
+                Ok(_) => files.push(path),
+                Err(_) => {}
+            }
+        }
+    }
+    let mut files = vec![];
+    walk(root, Path::new(""), &mut files);
+    files
+}
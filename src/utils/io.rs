@@ -0,0 +1,75 @@
+use std::{fs::File, io::Read, path::Path};
+
+use bytes::Bytes;
+use memmap2::Mmap;
+use tokio::sync::mpsc::Receiver;
+
+/// A blocking reader over chunks received from a channel, it ends when the channel is closed.
+pub struct ChannelReader {
+    rx: Receiver<Bytes>,
+    chunk: Bytes,
+}
+
+impl ChannelReader {
+    pub fn new(rx: Receiver<Bytes>) -> Self {
+        Self {
+            rx,
+            chunk: Bytes::new(),
+        }
+    }
+}
+
+impl Read for ChannelReader {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        while self.chunk.is_empty() {
+            match self.rx.blocking_recv() {
+                Some(chunk) => self.chunk = chunk,
+                None => return Ok(0),
+            }
+        }
+        let n = buf.len().min(self.chunk.len());
+        buf[..n].copy_from_slice(&self.chunk.split_to(n));
+        Ok(n)
+    }
+}
+
+/// Read the little-endian `u32` at `offset` of `data`, which must be long enough.
+pub fn read_u32(data: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes(data[offset..offset + 4].try_into().unwrap())
+}
+
+/// Read the little-endian `u64` at `offset` of `data`, which must be long enough.
+pub fn read_u64(data: &[u8], offset: usize) -> u64 {
+    u64::from_le_bytes(data[offset..offset + 8].try_into().unwrap())
+}
+
+/// Whether `data` starts with the header shared by the binary files of the cache, `magic`
+/// then `version` as a `u32`, and is at least `min_len` long.
+pub fn has_header(data: &[u8], magic: &[u8; 4], version: u32, min_len: usize) -> bool {
+    data.len() >= min_len.max(8) && &data[..4] == magic && read_u32(data, 4) == version
+}
+
+/// Map a binary file of the cache into memory.
+pub fn map_file(path: &Path) -> std::io::Result<Mmap> {
+    let file = File::open(path)?;
+    // SAFETY: the binary files of the cache are written to a temporary file then renamed
+    // into place, never modified in place, so a mapping always sees a complete file.
+    unsafe { Mmap::map(&file) }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn header() {
+        let mut data = b"DQXX".to_vec();
+        data.extend_from_slice(&2u32.to_le_bytes());
+        data.extend_from_slice(&[0; 8]);
+        assert!(has_header(&data, b"DQXX", 2, 16));
+        assert!(!has_header(&data, b"DQYY", 2, 16));
+        assert!(!has_header(&data, b"DQXX", 1, 16));
+        assert!(!has_header(&data, b"DQXX", 2, 17));
+        assert!(!has_header(&data[..6], b"DQXX", 2, 0));
+    }
+}
@@ -1 +1,6 @@
+pub mod fs;
+pub mod io;
+pub mod pager;
 pub mod progress;
+#[cfg(test)]
+pub mod testing;
@@ -0,0 +1,56 @@
+use std::{
+    io::{IsTerminal, Write},
+    process::{Command, Stdio},
+};
+
+use crate::config::Config;
+
+/// The pager to use: `$DQ_PAGER`, the `pager` config key, `$PAGER`, then `less -R`.
+///
+/// `force` overrides whether to page at all, by default only when stdout is a terminal.
+/// An empty command disables the pager.
+pub fn pager_command(config: &Config, force: Option<bool>) -> Option<String> {
+    if !force.unwrap_or_else(|| std::io::stdout().is_terminal()) {
+        return None;
+    }
+    let command = std::env::var("DQ_PAGER")
+        .ok()
+        .or_else(|| config.pager.clone())
+        .or_else(|| std::env::var("PAGER").ok())
+        .unwrap_or_else(|| "less -R".to_string());
+    Some(command).filter(|command| !command.trim().is_empty())
+}
+
+/// Print `text` through `pager`, or straight to stdout if there is none or it can't be run.
+pub fn print_paged(text: &str, pager: Option<&str>) -> anyhow::Result<()> {
+    let Some(pager) = pager else {
+        print!("{}", text);
+        return Ok(());
+    };
+
+    let mut command = Command::new("sh");
+    command.arg("-c").arg(pager).stdin(Stdio::piped());
+    // Keep the colours with a bare `less`, and quit it if the text fits on one screen.
+    if std::env::var_os("LESS").is_none() {
+        command.env("LESS", "FRX");
+    }
+    if std::env::var_os("LV").is_none() {
+        command.env("LV", "-c");
+    }
+    let Ok(mut child) = command.spawn() else {
+        print!("{}", text);
+        return Ok(());
+    };
+
+    if let Some(mut stdin) = child.stdin.take() {
+        match stdin.write_all(text.as_bytes()) {
+            // The pager was closed before reading everything.
+            Err(e) if e.kind() == std::io::ErrorKind::BrokenPipe => {}
+            ret => ret?,
+        }
+    }
+    child
+        .wait()
+        .map_err(|e| anyhow::anyhow!("Run pager {}, err: {}", pager, e))?;
+    Ok(())
+}
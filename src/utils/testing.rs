@@ -0,0 +1,58 @@
+use std::path::Path;
+
+use crate::{config::Config, context::Context};
+
+/// Write a mirror of devdocs into `dir`, with one page and one entry per docset.
+pub fn write_mirror(dir: &Path, slugs: &[&str]) {
+    let docsets = slugs
+        .iter()
+        .map(|slug| {
+            serde_json::json!({
+                "name": slug,
+                "slug": slug,
+                "type": "simple",
+                "links": null,
+                "version": null,
+                "release": null,
+                "mtime": 1700000000,
+                "db_size": 0,
+            })
+        })
+        .collect::<Vec<_>>();
+    std::fs::create_dir_all(dir).unwrap();
+    std::fs::write(
+        dir.join("docs.json"),
+        serde_json::to_string(&docsets).unwrap(),
+    )
+    .unwrap();
+    for slug in slugs {
+        let index = serde_json::json!({
+            "entries": [{ "name": "Page", "path": "page", "type": "Type" }],
+            "types": [{ "name": "Type", "count": 1, "slug": "type" }],
+        });
+        let db = serde_json::json!({ "page": "<h1>Page</h1><p>Some text.</p>" });
+        std::fs::create_dir_all(dir.join(slug)).unwrap();
+        std::fs::write(dir.join(slug).join("index.json"), index.to_string()).unwrap();
+        std::fs::write(dir.join(slug).join("db.json"), db.to_string()).unwrap();
+    }
+}
+
+/// A context with its cache in `dir/cache`, downloading from the mirror in `dir/mirror`.
+pub async fn context(dir: &Path) -> Context {
+    let mirror = dir.join("mirror");
+    let config = Config {
+        cache_dir: Some(dir.join("cache")),
+        progress: Some(false),
+        meta_url: Some(format!("file://{}", mirror.join("docs.json").display())),
+        documents_url: Some(format!("file://{}", mirror.display())),
+        ..Default::default()
+    };
+    Context::new(config).await.unwrap()
+}
+
+/// Parse the arguments of a command as if they were given on the command line.
+pub fn parse_args<T: clap::Args + clap::FromArgMatches>(args: &[&str]) -> T {
+    let command = T::augment_args(clap::Command::new("dq"));
+    let matches = command.get_matches_from(std::iter::once("dq").chain(args.iter().copied()));
+    T::from_arg_matches(&matches).unwrap()
+}